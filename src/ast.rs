@@ -4,11 +4,14 @@ use crate::file_info::FI;
 pub type Stmt = Statement;
 pub type Exp = Expression;
 pub type Op = Operator;
+pub type UnOp = UnaryOperator;
 
 #[derive(Debug, PartialEq)]
 pub struct Program {
     pub functions: Vec<Function>,
     pub globals: Vec<Global>,
+    pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -27,16 +30,49 @@ pub struct Parameter {
     pub fi: FI,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<StructField>,
+    pub fi: FI,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StructField {
+    pub name: String,
+    pub ttype: Type_,
+    pub fi: FI,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Enum {
+    pub name: String,
+    pub variants: Vec<Variant>,
+    pub fi: FI,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    // the types of `Circle(f64)`, empty for variants without a payload
+    pub payload: Vec<Type_>,
+    pub fi: FI,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Statement {
     If(If),
     While(While),
     DoWhile(DoWhile),
+    For(Box<For>),
     Let(Let),
     Asm(Asm),
     Return(Return),
     Assign(Assign),
     Call(Call),
+    Match(Match),
+    Break(FI),
+    Continue(FI),
 }
 
 #[derive(Debug, PartialEq)]
@@ -61,6 +97,32 @@ pub struct DoWhile {
     pub fi: FI,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct For {
+    pub init: Let,
+    pub condition: Expression,
+    pub step: Assign,
+    pub body: Vec<Statement>,
+    pub fi: FI,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Match {
+    pub value: Expression,
+    pub arms: Vec<MatchArm>,
+    pub fi: FI,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MatchArm {
+    // None for `_`, it matches every variant without an arm of its own
+    pub variant: Option<String>,
+    // names for the payload values, `_` skips one
+    pub bindings: Vec<String>,
+    pub body: Vec<Statement>,
+    pub fi: FI,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Let {
     pub name: String,
@@ -98,7 +160,8 @@ pub struct Return {
 
 #[derive(Debug, PartialEq)]
 pub struct Assign {
-    pub name: String,
+    // a variable, dereference, index or field, the place that gets the value
+    pub target: Expression,
     pub value: Expression,
     pub fi: FI,
 }
@@ -107,8 +170,26 @@ pub struct Assign {
 pub enum Expression {
     U64(u64, FI),
     I64(i64, FI),
+    U32(u32, FI),
+    I32(i32, FI),
+    U16(u16, FI),
+    I16(i16, FI),
+    U8(u8, FI),
+    I8(i8, FI),
+    F64(f64, FI),
+    Bool(bool, FI),
+    Str(Vec<u8>, FI),
     Var(String, FI),
     BinOp(Box<Expression>, Operator, Box<Expression>, FI),
+    UnaryOp(UnaryOperator, Box<Expression>, FI),
+    Index(Box<Expression>, Box<Expression>, FI),
+    Array(Vec<Expression>, FI),
+    // `Point { x: 1, y: 2 }`, fields in the order they are written
+    Struct(String, Vec<(String, Expression)>, FI),
+    // `Shape::Circle(1.5)`, the enum, the variant and its payload
+    Variant(String, String, Vec<Expression>, FI),
+    Field(Box<Expression>, String, FI),
+    Cast(Box<Expression>, Type_, FI),
     Call(Call),
 }
 
@@ -123,12 +204,44 @@ pub enum Operator {
     Ne,
     LT,
     GT,
+    LE,
+    GE,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    BitNot,
+    Neg,
+    Deref,
+    AddrOf,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Type_ {
     U64(FI),
     I64(FI),
+    U32(FI),
+    I32(FI),
+    U16(FI),
+    I16(FI),
+    U8(FI),
+    I8(FI),
+    F64(FI),
+    Bool(FI),
+    Ptr(Box<Type_>, FI),
+    Array(Box<Type_>, u64, FI),
+    // a pointer and a length, `.ptr` and `.len`
+    Str(FI),
+    // a struct or enum by name, its layout comes from the declaration
+    Named(String, FI),
 }
 
 #[derive(Debug, PartialEq)]
@@ -143,11 +256,81 @@ impl Expression {
         match self {
             Expression::U64(_, fi) => *fi,
             Expression::I64(_, fi) => *fi,
+            Expression::U32(_, fi) => *fi,
+            Expression::I32(_, fi) => *fi,
+            Expression::U16(_, fi) => *fi,
+            Expression::I16(_, fi) => *fi,
+            Expression::U8(_, fi) => *fi,
+            Expression::I8(_, fi) => *fi,
+            Expression::F64(_, fi) => *fi,
+            Expression::Bool(_, fi) => *fi,
+            Expression::Str(_, fi) => *fi,
             Expression::Var(_, fi) => *fi,
             Expression::BinOp(_, _, _, fi) => *fi,
+            Expression::UnaryOp(_, _, fi) => *fi,
+            Expression::Index(_, _, fi) => *fi,
+            Expression::Array(_, fi) => *fi,
+            Expression::Struct(_, _, fi) => *fi,
+            Expression::Variant(_, _, _, fi) => *fi,
+            Expression::Field(_, _, fi) => *fi,
+            Expression::Cast(_, _, fi) => *fi,
             Expression::Call(call) => call.fi,
         }
     }
+
+    /// The same expression covering a different span, e.g. including surrounding parentheses.
+    pub fn with_fi(self, fi: FI) -> Expression {
+        match self {
+            Expression::U64(n, _) => Expression::U64(n, fi),
+            Expression::I64(n, _) => Expression::I64(n, fi),
+            Expression::U32(n, _) => Expression::U32(n, fi),
+            Expression::I32(n, _) => Expression::I32(n, fi),
+            Expression::U16(n, _) => Expression::U16(n, fi),
+            Expression::I16(n, _) => Expression::I16(n, fi),
+            Expression::U8(n, _) => Expression::U8(n, fi),
+            Expression::I8(n, _) => Expression::I8(n, fi),
+            Expression::F64(n, _) => Expression::F64(n, fi),
+            Expression::Bool(b, _) => Expression::Bool(b, fi),
+            Expression::Str(bytes, _) => Expression::Str(bytes, fi),
+            Expression::Var(name, _) => Expression::Var(name, fi),
+            Expression::BinOp(e1, op, e2, _) => Expression::BinOp(e1, op, e2, fi),
+            Expression::UnaryOp(op, e, _) => Expression::UnaryOp(op, e, fi),
+            Expression::Index(array, index, _) => Expression::Index(array, index, fi),
+            Expression::Array(elements, _) => Expression::Array(elements, fi),
+            Expression::Struct(name, fields, _) => Expression::Struct(name, fields, fi),
+            Expression::Variant(name, variant, payload, _) => Expression::Variant(name, variant, payload, fi),
+            Expression::Field(base, name, _) => Expression::Field(base, name, fi),
+            Expression::Cast(e, ttype, _) => Expression::Cast(e, ttype, fi),
+            Expression::Call(call) => Expression::Call(Call { fi, ..call }),
+        }
+    }
+
+    /// Expressions that name a memory location, they can be assigned to and have their address taken.
+    pub fn is_place(&self) -> bool {
+        match self {
+            Expression::Var(_, _) | Expression::UnaryOp(UnOp::Deref, _, _) | Expression::Index(_, _, _) => true,
+            Expression::Field(base, _, _) => base.is_place(),
+            _ => false,
+        }
+    }
+}
+
+impl Operator {
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, Op::Eq | Op::Ne | Op::LT | Op::GT | Op::LE | Op::GE)
+    }
+    /// The comparison that holds exactly when `self` does not.
+    pub fn negated_comparison(&self) -> Operator {
+        match self {
+            Op::Eq => Op::Ne,
+            Op::Ne => Op::Eq,
+            Op::LT => Op::GE,
+            Op::GT => Op::LE,
+            Op::LE => Op::GT,
+            Op::GE => Op::LT,
+            _ => panic!("not a comparison, {:?}", self),
+        }
+    }
 }
 
 impl Statement {
@@ -156,11 +339,15 @@ impl Statement {
             Statement::If(if_) => if_.fi,
             Statement::While(while_) => while_.fi,
             Statement::DoWhile(do_while) => do_while.fi,
+            Statement::For(for_stmt) => for_stmt.fi,
             Statement::Let(let_) => let_.fi,
             Statement::Asm(asm) => asm.fi,
             Statement::Return(return_) => return_.fi,
             Statement::Assign(assign) => assign.fi,
             Statement::Call(call) => call.fi,
+            Statement::Match(match_) => match_.fi,
+            Statement::Break(fi) => *fi,
+            Statement::Continue(fi) => *fi,
         }
     }
 }
@@ -170,14 +357,77 @@ impl Type_ {
         match self {
             Type_::U64(fi) => *fi,
             Type_::I64(fi) => *fi,
+            Type_::U32(fi) => *fi,
+            Type_::I32(fi) => *fi,
+            Type_::U16(fi) => *fi,
+            Type_::I16(fi) => *fi,
+            Type_::U8(fi) => *fi,
+            Type_::I8(fi) => *fi,
+            Type_::F64(fi) => *fi,
+            Type_::Bool(fi) => *fi,
+            Type_::Ptr(_, fi) => *fi,
+            Type_::Array(_, _, fi) => *fi,
+            Type_::Str(fi) => *fi,
+            Type_::Named(_, fi) => *fi,
         }
     }
     pub fn zero(&self) -> Type_ {
         match self {
             Type_::U64(_) => Type_::U64(FI::zero()),
             Type_::I64(_) => Type_::I64(FI::zero()),
+            Type_::U32(_) => Type_::U32(FI::zero()),
+            Type_::I32(_) => Type_::I32(FI::zero()),
+            Type_::U16(_) => Type_::U16(FI::zero()),
+            Type_::I16(_) => Type_::I16(FI::zero()),
+            Type_::U8(_) => Type_::U8(FI::zero()),
+            Type_::I8(_) => Type_::I8(FI::zero()),
+            Type_::F64(_) => Type_::F64(FI::zero()),
+            Type_::Bool(_) => Type_::Bool(FI::zero()),
+            Type_::Ptr(inner, _) => Type_::Ptr(Box::new(inner.zero()), FI::zero()),
+            Type_::Array(elem, len, _) => Type_::Array(Box::new(elem.zero()), *len, FI::zero()),
+            Type_::Str(_) => Type_::Str(FI::zero()),
+            Type_::Named(name, _) => Type_::Named(name.clone(), FI::zero()),
         }
     }
+    /// Size in bytes of a value of this type in memory.
+    /// Struct and enum sizes depend on their declaration, see `ProgEnv::size_of`.
+    pub fn size(&self) -> usize {
+        match self {
+            Type_::U64(_) | Type_::I64(_) | Type_::F64(_) | Type_::Bool(_) | Type_::Ptr(_, _) => 8,
+            Type_::U32(_) | Type_::I32(_) => 4,
+            Type_::U16(_) | Type_::I16(_) => 2,
+            Type_::U8(_) | Type_::I8(_) => 1,
+            Type_::Array(elem, len, _) => elem.size() * *len as usize,
+            Type_::Str(_) => 16,
+            Type_::Named(name, _) => panic!("size of {} depends on its declaration", name),
+        }
+    }
+    /// The scalar type stored at the leaves, e.g. `u8` for `[[u8; 2]; 3]`.
+    pub fn scalar(&self) -> &Type_ {
+        match self {
+            Type_::Array(elem, _, _) => elem.scalar(),
+            _ => self,
+        }
+    }
+    /// Values that do not fit in a register, they are handled through their address.
+    pub fn is_aggregate(&self) -> bool {
+        matches!(self, Type_::Array(_, _, _) | Type_::Str(_) | Type_::Named(_, _))
+    }
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Type_::U64(_) | Type_::I64(_) | Type_::U32(_) | Type_::I32(_) | Type_::U16(_) | Type_::I16(_) | Type_::U8(_) | Type_::I8(_)
+        )
+    }
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type_::I64(_) | Type_::I32(_) | Type_::I16(_) | Type_::I8(_))
+    }
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type_::F64(_))
+    }
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, Type_::Ptr(_, _))
+    }
     pub fn eq(&self, other: &Type_) -> bool {
         let a = self.zero();
         let b = other.zero();
@@ -188,6 +438,28 @@ impl Type_ {
     }
 }
 
+/// Types are printed the way they are written in the source, `*[u8; 4]`.
+impl std::fmt::Display for Type_ {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type_::U64(_) => write!(f, "u64"),
+            Type_::I64(_) => write!(f, "i64"),
+            Type_::U32(_) => write!(f, "u32"),
+            Type_::I32(_) => write!(f, "i32"),
+            Type_::U16(_) => write!(f, "u16"),
+            Type_::I16(_) => write!(f, "i16"),
+            Type_::U8(_) => write!(f, "u8"),
+            Type_::I8(_) => write!(f, "i8"),
+            Type_::F64(_) => write!(f, "f64"),
+            Type_::Bool(_) => write!(f, "bool"),
+            Type_::Ptr(inner, _) => write!(f, "*{}", inner),
+            Type_::Array(elem, len, _) => write!(f, "[{}; {}]", elem, len),
+            Type_::Str(_) => write!(f, "str"),
+            Type_::Named(name, _) => write!(f, "{}", name),
+        }
+    }
+}
+
 
 pub fn binop(left: Exp, op: Op, right: Exp) -> Exp {
     let left_fi = left.fi();
@@ -195,3 +467,8 @@ pub fn binop(left: Exp, op: Op, right: Exp) -> Exp {
     let fi = left_fi.merge(&right_fi);
     Exp::BinOp(Box::new(left), op, Box::new(right), fi)
 }
+
+pub fn unary_op(op: UnOp, exp: Exp, start_fi: FI) -> Exp {
+    let fi = start_fi.merge(&exp.fi());
+    Exp::UnaryOp(op, Box::new(exp), fi)
+}
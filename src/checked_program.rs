@@ -1,18 +1,65 @@
 use crate::ast::*;
-use crate::file_info::{FI, underline_error};
+use crate::diagnostic::Diagnostic;
+use crate::file_info::FI;
 
 #[derive(Debug)]
 pub struct CheckedProgram {
     pub prog: Program,
     pub program_env: ProgEnv,
     pub function_envs: Vec<FuncEnv>,
+    // problems that do not stop the compilation
+    pub warnings: Vec<CheckError>,
 }
 
 #[derive(Debug)]
 pub struct ProgEnv {
     fn_sigs: Vec<FuncSig>,
-    pub global_values: Vec<i64>,
+    // every global is a list of scalar values, one for scalars and one per element for arrays
+    pub global_values: Vec<Vec<i64>>,
     pub globals_def: Vec<Variable>,
+    pub types: TypeDefs,
+}
+
+/// The layouts of the struct and enum declarations, they share one namespace.
+#[derive(Debug, Default)]
+pub struct TypeDefs {
+    pub structs: Vec<StructDef>,
+    pub enums: Vec<EnumDef>,
+}
+
+/// The memory layout of a struct declaration.
+#[derive(Debug)]
+pub struct StructDef {
+    pub name: String,
+    pub fields: Vec<FieldDef>,
+    pub size: usize,
+    pub align: usize,
+}
+
+#[derive(Debug)]
+pub struct FieldDef {
+    pub name: String,
+    pub ttype: Type_,
+    // bytes from the start of the struct
+    pub offset: usize,
+}
+
+/// The memory layout of an enum declaration, a u64 tag followed by the payload of the variant it holds.
+#[derive(Debug)]
+pub struct EnumDef {
+    pub name: String,
+    pub variants: Vec<VariantDef>,
+    pub size: usize,
+    pub align: usize,
+}
+
+#[derive(Debug)]
+pub struct VariantDef {
+    pub name: String,
+    // the value stored in the tag, the position of the variant in the declaration
+    pub tag: u64,
+    // payload values are named by their position, their offsets count from the start of the enum
+    pub payload: Vec<FieldDef>,
 }
 
 #[derive(Debug)]
@@ -20,6 +67,7 @@ pub struct FuncSig {
     pub name: String,
     pub params: Vec<Type_>,
     pub ret_type: Type_,
+    pub fi: FI,
 }
 
 #[derive(Debug)]
@@ -31,39 +79,51 @@ pub struct Variable {
 #[derive(Debug)]
 pub struct FuncEnv {
     pub function_params: Vec<Variable>,
+    // one stack slot per let in the function, in the order blocks are walked
     pub local_variables: Vec<Variable>,
+    // bytes used by each parameter and local, structs need the program's declarations to know them
+    param_sizes: Vec<usize>,
+    local_sizes: Vec<usize>,
+    // where each local is declared, the code generator finds the slots by it
+    local_fis: Vec<FI>,
     pub ret_type: Type_,
+    // open blocks, innermost block last
+    scopes: Vec<Scope>,
+    // statements that failed, the ones after them are still checked
+    errors: Vec<CheckError>,
+    warnings: Vec<CheckError>,
 }
 
-#[derive(Debug)]
-pub struct CheckError {
-    pub msg: String,
-    pub fi: FI,
+#[derive(Debug, Default)]
+struct Scope {
+    locals: Vec<usize>,
+    // lets further down the block, to tell "not declared yet" apart from "not found"
+    upcoming: Vec<String>,
 }
 
-impl CheckError {
-    pub fn new(msg: &str, fi: FI) -> CheckError {
-        CheckError { msg: msg.to_string(), fi }
-    }
-    pub fn pretty_print(&self, input: &str) {
-        println!("{}", self.msg);
-        println!("{}", underline_error(input, &self.fi));
-    }
-}
+pub type CheckError = Box<Diagnostic>;
 
 impl CheckedProgram {
-    pub fn check(prog: Program) -> Result<CheckedProgram, CheckError> {
+    /// Reports every error it finds, not only the first one. Each function is checked on its
+    /// own, the declarations they depend on have to be correct first.
+    pub fn check(prog: Program) -> Result<CheckedProgram, Vec<CheckError>> {
+        let mut errors = Vec::new();
         let mut function_envs = Vec::new();
         let mut fn_sigs: Vec<FuncSig> = Vec::new();
 
         for function in &prog.functions {
-            if fn_sigs.iter().any(|x| x.name == function.name) {
-                return Err(CheckError::new(&format!("Duplicate function name {}", function.name), function.fi));
+            if let Some(first) = fn_sigs.iter().find(|x| x.name == function.name) {
+                errors.push(
+                    Diagnostic::new(&format!("Duplicate function name {}", function.name), function.fi)
+                        .with_secondary(first.fi, "first declared here")
+                );
+                continue;
             }
             fn_sigs.push(FuncSig {
                 name: function.name.clone(),
                 params: function.params.iter().map(|x| x.ttype.clone()).collect(),
-                ret_type: function.ret_type,
+                ret_type: function.ret_type.clone(),
+                fi: function.fi,
             });
         }
 
@@ -71,10 +131,31 @@ impl CheckedProgram {
             .iter()
             .any(|x| x.name == "main" && x.params.is_empty());
         if !has_main {
-            return Err(CheckError::new("No main function found", FI::zero()));
+            errors.push(Diagnostic::new("No main function found", FI::zero()));
         }
 
-        let global_values = Self::resolve_global_values(&prog)?;
+        let types = match Self::resolve_types(&prog) {
+            Ok(types) => types,
+            Err(e) => {
+                errors.push(e);
+                return Err(errors);
+            }
+        };
+        let type_errors = errors.len();
+        for global in &prog.globals {
+            if let Err(e) = Self::check_type(&global.ttype, &types) {
+                errors.push(e);
+            } else if Self::contains_enum(&global.ttype, &types) {
+                errors.push(
+                    Diagnostic::new(&format!("Global {} can not hold an enum", global.name), global.ttype.fi())
+                        .with_note("the layout of an enum depends on the variant it holds, they are only built at run time")
+                        .with_help("build it in a function instead")
+                );
+            }
+        }
+        if errors.len() > type_errors {
+            return Err(errors);
+        }
         let mut globals_def = Vec::new();
         for global in &prog.globals {
             globals_def.push(Variable {
@@ -83,129 +164,591 @@ impl CheckedProgram {
             });
         }
 
-        let program_env = ProgEnv {
+        let mut program_env = ProgEnv {
             fn_sigs,
-            global_values,
+            global_values: vec![Vec::new(); prog.globals.len()],
             globals_def,
+            types,
         };
+        program_env.global_values = Self::resolve_global_values(&prog, &program_env, &mut errors);
 
+        let mut warnings = Vec::new();
         for function in &prog.functions {
-            let function_env = Self::check_function(function, &program_env)?;
-            function_envs.push(function_env);
+            match Self::check_function(function, &program_env) {
+                Ok(mut function_env) => {
+                    warnings.append(&mut function_env.warnings);
+                    function_envs.push(function_env);
+                }
+                Err(mut function_errors) => errors.append(&mut function_errors),
+            }
+        }
+
+        if !errors.is_empty() {
+            // the warnings are reported with the errors, see `Diagnostic::is_error`
+            errors.append(&mut warnings);
+            return Err(errors);
         }
 
         Ok(CheckedProgram {
             prog,
             program_env,
             function_envs,
+            warnings,
         })
     }
 
-    fn resolve_global_values(prog: &Program) -> Result<Vec<i64>, CheckError> {
-        let dependencies: Vec<Vec<usize>> = Self::find_global_dependencies(prog);
+    /// Lays out every struct and enum, the ones a type contains by value go first.
+    fn resolve_types(prog: &Program) -> Result<TypeDefs, CheckError> {
+        let names: Vec<(&String, FI)> = prog
+            .structs
+            .iter()
+            .map(|x| (&x.name, x.fi))
+            .chain(prog.enums.iter().map(|x| (&x.name, x.fi)))
+            .collect();
+        for (i, (name, fi)) in names.iter().enumerate() {
+            if names[..i].iter().any(|(x, _)| x == name) {
+                return Err(Diagnostic::new(&format!("Duplicate type name {}", name), *fi));
+            }
+        }
+        for s in &prog.structs {
+            for (j, field) in s.fields.iter().enumerate() {
+                if s.fields[..j].iter().any(|x| x.name == field.name) {
+                    return Err(
+                        Diagnostic::new(&format!("Duplicate field name {} in struct {}", field.name, s.name), field.fi)
+                    );
+                }
+            }
+        }
+        for e in &prog.enums {
+            if e.variants.is_empty() {
+                return Err(Diagnostic::new(&format!("Enum {} has no variants", e.name), e.fi));
+            }
+            for (j, variant) in e.variants.iter().enumerate() {
+                if e.variants[..j].iter().any(|x| x.name == variant.name) {
+                    return Err(
+                        Diagnostic::new(&format!("Duplicate variant name {} in enum {}", variant.name, e.name), variant.fi)
+                    );
+                }
+            }
+        }
+
+        let mut defs = TypeDefs::default();
+        for (name, fi) in &names {
+            Self::layout_named(name, *fi, prog, &mut defs, &mut Vec::new())?;
+        }
+        // pointers do not need the layout of what they point to, only that it exists
+        for s in &prog.structs {
+            for field in &s.fields {
+                Self::check_type(&field.ttype, &defs)?;
+            }
+        }
+        for e in &prog.enums {
+            for ttype in e.variants.iter().flat_map(|x| &x.payload) {
+                Self::check_type(ttype, &defs)?;
+            }
+        }
+        Ok(defs)
+    }
+
+    fn layout_named(
+        name: &str,
+        fi: FI,
+        prog: &Program,
+        defs: &mut TypeDefs,
+        visiting: &mut Vec<String>,
+    ) -> Result<(), CheckError> {
+        if defs.structs.iter().any(|x| x.name == name) || defs.enums.iter().any(|x| x.name == name) {
+            return Ok(());
+        }
+        let s = prog.structs.iter().find(|x| x.name == name);
+        let e = prog.enums.iter().find(|x| x.name == name);
+        let contained: Vec<&Type_> = match (s, e) {
+            (Some(s), _) => s.fields.iter().map(|x| &x.ttype).collect(),
+            (_, Some(e)) => e.variants.iter().flat_map(|x| &x.payload).collect(),
+            _ => return Err(Diagnostic::new(&format!("Unknown type {}", name), fi)),
+        };
+        if visiting.iter().any(|x| x == name) {
+            return Err(
+                Diagnostic::new(&format!("Type {} contains itself, use a pointer instead", name), fi)
+            );
+        }
+        visiting.push(name.to_string());
+        for ttype in contained {
+            Self::layout_inline_types(ttype, prog, defs, visiting)?;
+        }
+        visiting.pop();
+
+        if let Some(s) = s {
+            let (offsets, end, align) = Self::layout_fields(s.fields.iter().map(|x| &x.ttype), 0, 1, defs);
+            let fields = s
+                .fields
+                .iter()
+                .zip(offsets)
+                .map(|(field, offset)| FieldDef { name: field.name.clone(), ttype: field.ttype.clone(), offset })
+                .collect();
+            defs.structs.push(StructDef { name: name.to_string(), fields, size: end.next_multiple_of(align), align });
+            return Ok(());
+        }
+
+        // every variant starts its payload right after the tag, the largest one decides the size
+        let e = e.unwrap();
+        let mut variants = Vec::new();
+        let mut size = 8;
+        let mut align = 8;
+        for (tag, variant) in e.variants.iter().enumerate() {
+            let (offsets, end, variant_align) = Self::layout_fields(variant.payload.iter(), 8, 8, defs);
+            let payload = variant
+                .payload
+                .iter()
+                .zip(offsets)
+                .enumerate()
+                .map(|(i, (ttype, offset))| FieldDef { name: i.to_string(), ttype: ttype.clone(), offset })
+                .collect();
+            variants.push(VariantDef { name: variant.name.clone(), tag: tag as u64, payload });
+            size = size.max(end);
+            align = align.max(variant_align);
+        }
+        defs.enums.push(EnumDef { name: name.to_string(), variants, size: size.next_multiple_of(align), align });
+        Ok(())
+    }
+
+    /// Places the types one after the other from `start`, each aligned to its own alignment.
+    /// Returns their offsets, the end of the last one and the largest alignment.
+    fn layout_fields<'a>(
+        types: impl Iterator<Item = &'a Type_>,
+        start: usize,
+        min_align: usize,
+        defs: &TypeDefs,
+    ) -> (Vec<usize>, usize, usize) {
+        let mut offsets = Vec::new();
+        let mut offset = start;
+        let mut align = min_align;
+        for ttype in types {
+            let field_align = align_of(ttype, defs);
+            offset = offset.next_multiple_of(field_align);
+            offsets.push(offset);
+            offset += size_of(ttype, defs);
+            align = align.max(field_align);
+        }
+        (offsets, offset, align)
+    }
+
+    fn layout_inline_types(
+        ttype: &Type_,
+        prog: &Program,
+        defs: &mut TypeDefs,
+        visiting: &mut Vec<String>,
+    ) -> Result<(), CheckError> {
+        match ttype {
+            Type_::Named(name, fi) => Self::layout_named(name, *fi, prog, defs, visiting),
+            Type_::Array(elem, _, _) => Self::layout_inline_types(elem, prog, defs, visiting),
+            _ => Ok(()),
+        }
+    }
+
+    /// Every struct and enum named in a type must be declared.
+    fn check_type(ttype: &Type_, types: &TypeDefs) -> Result<(), CheckError> {
+        match ttype {
+            Type_::Named(name, fi) if types.get_struct(name).is_none() && types.get_enum(name).is_none() => {
+                Err(Diagnostic::new(&format!("Unknown type {}", name), *fi))
+            }
+            Type_::Ptr(inner, _) | Type_::Array(inner, _, _) => Self::check_type(inner, types),
+            _ => Ok(()),
+        }
+    }
+
+    /// Enums held by value, directly or in an array or struct. Pointers to them do not count.
+    fn contains_enum(ttype: &Type_, types: &TypeDefs) -> bool {
+        match ttype {
+            Type_::Named(name, _) => match types.get_struct(name) {
+                Some(def) => def.fields.iter().any(|field| Self::contains_enum(&field.ttype, types)),
+                None => true,
+            },
+            Type_::Array(elem, _, _) => Self::contains_enum(elem, types),
+            _ => false,
+        }
+    }
+
+    /// The value of a global is checked like the value of a `let`, except that integer literals
+    /// without a suffix take the type of the global, like `global size: u64 = 4096;`. Their
+    /// values have to fit in it, narrow types are not silently truncated.
+    fn check_global(global: &Global, values: &[i64], p_env: &ProgEnv) -> Result<(), CheckError> {
+        Self::check_global_value(&global.value, &global.ttype, &Self::globals_env(), p_env)?;
+        for (value, ttype) in values.iter().zip(Self::scalar_types(&global.ttype, &p_env.types)) {
+            // values are folded as i64, a negative one is out of range for u64 even though its bits fit
+            let fits = match ttype {
+                Type_::U64(_) => *value >= 0,
+                _ => Self::wrap_to_type(*value, &ttype) == *value,
+            };
+            if !fits {
+                return Err(
+                    Diagnostic::new(&format!("Value {} of global {} does not fit in `{}`", value, global.name, ttype), global.value.fi())
+                        .with_secondary(ttype.fi(), "expected because of this type")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Array and struct literals are checked element by element, so the literals inside them
+    /// also take the type they are stored as.
+    fn check_global_value(value: &Exp, expected: &Type_, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<(), CheckError> {
+        match (value, expected) {
+            (Exp::Array(elements, _), Type_::Array(elem, _, _)) => {
+                for element in elements {
+                    Self::check_global_value(element, elem, f_env, p_env)?;
+                }
+                Ok(())
+            }
+            (Exp::Struct(name, fields, _), Type_::Named(expected_name, _)) if name == expected_name => {
+                let def = p_env
+                    .get_struct(name)
+                    .ok_or(Diagnostic::new(&format!("Unknown struct {}", name), value.fi()))?;
+                for (field_name, field_value) in fields {
+                    let field = def.get_field(field_name).ok_or(
+                        Diagnostic::new(&format!("Struct {} has no field {}", name, field_name), field_value.fi())
+                    )?;
+                    Self::check_global_value(field_value, &field.ttype, f_env, p_env)?;
+                }
+                Ok(())
+            }
+            _ => {
+                let found = Self::check_expression(value, f_env, p_env)?;
+                let untyped = expected.is_integer() && matches!(found, Type_::I64(_)) && Self::is_untyped_integer(value);
+                if found.neq(expected) && !untyped {
+                    return Err(
+                        Self::mismatch("global", value, &found, expected)
+                            .with_secondary(expected.fi(), "expected because of this type")
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Globals see no locals, the environment is only there for `check_expression`.
+    fn globals_env() -> FuncEnv {
+        FuncEnv {
+            function_params: Vec::new(),
+            local_variables: Vec::new(),
+            param_sizes: Vec::new(),
+            local_sizes: Vec::new(),
+            local_fis: Vec::new(),
+            ret_type: Type_::U64(FI::zero()),
+            scopes: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Integer literals without a suffix and arithmetic on only them.
+    fn is_untyped_integer(exp: &Exp) -> bool {
+        match exp {
+            Exp::I64(_, _) => true,
+            Exp::UnaryOp(UnOp::Neg | UnOp::BitNot, e, _) => Self::is_untyped_integer(e),
+            Exp::BinOp(e1, _, e2, _) => Self::is_untyped_integer(e1) && Self::is_untyped_integer(e2),
+            _ => false,
+        }
+    }
+
+    /// Evaluates every global it can, each failing one records its error and the globals that
+    /// depend on it are skipped, their values would only repeat it.
+    fn resolve_global_values(prog: &Program, p_env: &ProgEnv, errors: &mut Vec<CheckError>) -> Vec<Vec<i64>> {
+        let mut failed = vec![false; prog.globals.len()];
+        let dependencies: Vec<Vec<usize>> = Self::find_global_dependencies(prog, &mut failed, errors);
 
         let mut state: Vec<usize> = vec![0; prog.globals.len()];
         // 0 - not visited, 1 - visiting, 2 - visited
         let mut stack = Vec::new();
+        let mut path = Vec::new();
 
         for i in 0..prog.globals.len() {
             if state[i] == 0 {
-                Self::global_dfs(i, &dependencies, &mut state, &mut stack);
+                Self::global_dfs(i, &dependencies, &mut state, &mut stack, &mut path, &prog.globals, &mut failed, errors);
             }
         }
 
-        let mut global_values = vec![0; prog.globals.len()]; // will be filled with the values of the globals
-        let names: Vec<String> = prog.globals.iter().map(|x| x.name.clone()).collect();
-        for i in 0..prog.globals.len() {
-            let global = &prog.globals[stack[i] as usize];
-            global_values[stack[i]] =
-                Self::eval_global_expression(&global.value, &global_values, &names);
+        let mut global_values = vec![Vec::new(); prog.globals.len()]; // will be filled with the values of the globals
+        let f_env = Self::globals_env();
+        for i in stack {
+            if failed[i] || dependencies[i].iter().any(|x| failed[*x]) {
+                failed[i] = true;
+                continue;
+            }
+            let global = &prog.globals[i];
+            match Self::eval_global(global, &global_values, &f_env, p_env) {
+                Ok(values) => global_values[i] = values,
+                Err(e) => {
+                    errors.push(e);
+                    failed[i] = true;
+                }
+            }
+        }
+
+        global_values
+    }
+
+    /// Evaluating first reports globals used before they exist as globals, not as variables.
+    /// The type is checked before the value is stored, a value of the wrong type would be stored
+    /// as whatever bits it evaluates to.
+    fn eval_global(global: &Global, global_values: &Vec<Vec<i64>>, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<Vec<i64>, CheckError> {
+        let values = Self::eval_global_value(&global.value, global_values, f_env, p_env)?;
+        let expected = Self::scalar_types(&global.ttype, &p_env.types).len();
+        if values.len() != expected {
+            return Err(
+                Diagnostic::new(&format!("Global {} has {} values, its type needs {}", global.name, values.len(), expected), global.fi)
+            );
+        }
+        Self::check_global(global, &values, p_env)?;
+        Ok(values)
+    }
+
+    /// The type of every scalar in a value, in the order global values are flattened.
+    fn scalar_types(ttype: &Type_, types: &TypeDefs) -> Vec<Type_> {
+        match ttype {
+            Type_::Array(elem, len, _) => {
+                let elem_types = Self::scalar_types(elem, types);
+                (0..*len).flat_map(|_| elem_types.iter().cloned()).collect()
+            }
+            Type_::Named(name, _) => get_struct(name, types)
+                .fields
+                .iter()
+                .flat_map(|field| Self::scalar_types(&field.ttype, types))
+                .collect(),
+            _ => vec![ttype.clone()],
         }
+    }
 
-        Ok(global_values)
+    /// Array and struct literals and globals are flattened into their scalar values,
+    /// struct fields in declaration order.
+    fn eval_global_value(exp: &Exp, global_values: &Vec<Vec<i64>>, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<Vec<i64>, CheckError> {
+        match exp {
+            Exp::Array(elements, _) => {
+                let mut values = Vec::new();
+                for e in elements {
+                    values.append(&mut Self::eval_global_value(e, global_values, f_env, p_env)?);
+                }
+                Ok(values)
+            }
+            Exp::Struct(name, fields, fi) => {
+                let def = p_env
+                    .get_struct(name)
+                    .ok_or_else(|| Diagnostic::new(&format!("Unknown struct {} in global expression", name), *fi))?;
+                let mut values = Vec::new();
+                for field in &def.fields {
+                    let (_, value) = fields
+                        .iter()
+                        .find(|(n, _)| *n == field.name)
+                        .ok_or_else(|| Diagnostic::new(&format!("Missing field {} in global of struct {}", field.name, name), *fi))?;
+                    values.append(&mut Self::eval_global_value(value, global_values, f_env, p_env)?);
+                }
+                Ok(values)
+            }
+            Exp::Var(var, fi) => Ok(global_values[Self::global_index(var, p_env, *fi)?].clone()),
+            _ => Ok(vec![Self::eval_global_expression(exp, global_values, f_env, p_env)?]),
+        }
     }
 
-    fn eval_global_expression(exp: &Exp, global_values: &Vec<i64>, names: &Vec<String>) -> i64 {
+    /// Floats are stored as their bits, the only operation folded on them is negation. The type of
+    /// an operand is checked rather than its shape, globals read by name can be floats too.
+    fn eval_global_expression(exp: &Exp, global_values: &Vec<Vec<i64>>, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<i64, CheckError> {
+        let not_allowed = |what: &str| Err(Diagnostic::new(&format!("{} not allowed in global expressions", what), exp.fi()));
+        let is_float = |e: &Exp| Self::check_expression(e, f_env, p_env).is_ok_and(|t| t.is_float());
         match exp {
-            Exp::U64(_n, _) => panic!("Unsigned integers not allowed in global expressions"), 
-            Exp::I64(n, _) => *n,
-            Exp::Var(var, _) => {
-                let index = names.iter().position(|x| x == var);
-                global_values[index.unwrap()]
+            Exp::U64(_n, _) => not_allowed("Unsigned integers"),
+            Exp::I64(n, _) => Ok(*n),
+            Exp::U32(n, _) => Ok(*n as i64),
+            Exp::I32(n, _) => Ok(*n as i64),
+            Exp::U16(n, _) => Ok(*n as i64),
+            Exp::I16(n, _) => Ok(*n as i64),
+            Exp::U8(n, _) => Ok(*n as i64),
+            Exp::I8(n, _) => Ok(*n as i64),
+            Exp::F64(f, _) => Ok(f.to_bits() as i64),
+            Exp::UnaryOp(UnOp::Neg, e, _) if is_float(e) => {
+                // flips the sign bit, like the generated code does
+                Ok(Self::eval_global_expression(e, global_values, f_env, p_env)? ^ i64::MIN)
+            }
+            Exp::BinOp(e1, _, e2, _) if is_float(e1) || is_float(e2) => not_allowed("Floating point arithmetic"),
+            Exp::Cast(e, ttype, _) if ttype.is_float() || is_float(e) => not_allowed("Floating point casts"),
+            Exp::Bool(b, _) => Ok(*b as i64),
+            Exp::Var(var, fi) => {
+                let index = Self::global_index(var, p_env, *fi)?;
+                match global_values[index].as_slice() {
+                    [value] => Ok(*value),
+                    _ => Err(Diagnostic::new(&format!("Global {} is an array or struct, it can only be used as a whole value", var), *fi)),
+                }
             }
             Exp::BinOp(e1, op, e2, _) => {
-                let v1 = Self::eval_global_expression(e1, global_values, names);
-                let v2 = Self::eval_global_expression(e2, global_values, names);
+                let v1 = Self::eval_global_expression(e1, global_values, f_env, p_env)?;
+                let v2 = Self::eval_global_expression(e2, global_values, f_env, p_env)?;
+                match op {
+                    Op::Add => Ok(v1.wrapping_add(v2)),
+                    Op::Sub => Ok(v1.wrapping_sub(v2)),
+                    Op::Mul => Ok(v1.wrapping_mul(v2)),
+                    Op::Div | Op::Mod if v2 == 0 => Err(Diagnostic::new("Division by zero in global expression", exp.fi())),
+                    Op::Div => Ok(v1.wrapping_div(v2)),
+                    Op::Mod => Ok(v1.wrapping_rem(v2)),
+                    Op::And => Ok((v1 != 0 && v2 != 0) as i64),
+                    Op::Or => Ok((v1 != 0 || v2 != 0) as i64),
+                    Op::BitAnd => Ok(v1 & v2),
+                    Op::BitOr => Ok(v1 | v2),
+                    Op::BitXor => Ok(v1 ^ v2),
+                    Op::Shl => Ok(v1.wrapping_shl(v2 as u32)),
+                    Op::Shr => Ok(v1.wrapping_shr(v2 as u32)),
+                    Op::Eq | Op::Ne | Op::LT | Op::GT | Op::LE | Op::GE => not_allowed("Comparison operators"),
+                }
+            }
+            Exp::UnaryOp(op, e, _) => {
+                let v = Self::eval_global_expression(e, global_values, f_env, p_env)?;
                 match op {
-                    Op::Add => v1 + v2,
-                    Op::Sub => v1 - v2,
-                    Op::Mul => v1 * v2,
-                    Op::Div => v1 / v2,
-                    Op::Mod => v1 % v2,
-                    Op::Eq => panic!("Comparison operators not allowed in global expressions"),
-                    Op::Ne => panic!("Comparison operators not allowed in global expressions"),
-                    Op::LT => panic!("Comparison operators not allowed in global expressions"),
-                    Op::GT => panic!("Comparison operators not allowed in global expressions"),
+                    UnOp::Not => Ok((v == 0) as i64),
+                    UnOp::BitNot => Ok(!v),
+                    UnOp::Neg => Ok(v.wrapping_neg()),
+                    UnOp::Deref | UnOp::AddrOf => not_allowed("Pointer operators"),
                 }
             }
-            Exp::Call(_) => {
-                panic!("Function calls not allowed in global expressions");
+            Exp::Cast(e, ttype, _) => {
+                let v = Self::eval_global_expression(e, global_values, f_env, p_env)?;
+                Ok(Self::wrap_to_type(v, ttype))
             }
+            Exp::Index(_, _, _) => not_allowed("Indexing"),
+            Exp::Str(_, _) | Exp::Field(_, _, _) => not_allowed("Strings"),
+            Exp::Array(_, _) | Exp::Struct(_, _, _) => Err(Diagnostic::new(
+                "Array and struct literals are only allowed as the whole value of a global",
+                exp.fi(),
+            )),
+            Exp::Variant(_, _, _, _) => not_allowed("Enum values"),
+            Exp::Call(_) => not_allowed("Function calls"),
+        }
+    }
+
+    fn global_index(name: &str, p_env: &ProgEnv, fi: FI) -> Result<usize, CheckError> {
+        p_env
+            .globals_def
+            .iter()
+            .position(|x| x.name == name)
+            .ok_or_else(|| Diagnostic::new(&format!("Global {} not found, globals can only use other globals", name), fi))
+    }
+
+    /// The value a register holds after a cast to `ttype`, sign or zero extended from its width.
+    fn wrap_to_type(value: i64, ttype: &Type_) -> i64 {
+        match ttype {
+            Type_::U32(_) => value as u32 as i64,
+            Type_::I32(_) => value as i32 as i64,
+            Type_::U16(_) => value as u16 as i64,
+            Type_::I16(_) => value as i16 as i64,
+            Type_::U8(_) => value as u8 as i64,
+            Type_::I8(_) => value as i8 as i64,
+            _ => value,
         }
     }
 
+    /// Post order of the globals, so each one comes after the ones its value uses.
+    /// `path` holds the globals being visited, to report the whole cycle. The globals of a cycle
+    /// are marked as failed.
+    #[allow(clippy::too_many_arguments)]
     fn global_dfs(
         n: usize,
         deps: &Vec<Vec<usize>>,
         state: &mut Vec<usize>,
         stack: &mut Vec<usize>,
+        path: &mut Vec<usize>,
+        globals: &[Global],
+        failed: &mut [bool],
+        errors: &mut Vec<CheckError>,
     ) {
         state[n] = 1;
+        path.push(n);
         for dep in &deps[n] {
             if state[*dep] == 0 {
-                Self::global_dfs(*dep, deps, state, stack);
+                Self::global_dfs(*dep, deps, state, stack, path, globals, failed, errors);
             } else if state[*dep] == 1 {
-                panic!("Cyclic dependency in global variables");
+                let start = path.iter().position(|x| x == dep).unwrap();
+                let cycle = &path[start..];
+                let names: Vec<&str> = cycle
+                    .iter()
+                    .chain(std::iter::once(dep))
+                    .map(|x| globals[*x].name.as_str())
+                    .collect();
+                let mut error = Diagnostic::new(
+                    &format!("Cyclic dependency in global variables: {}", names.join(" -> ")),
+                    globals[*dep].fi,
+                );
+                for (i, x) in cycle.iter().enumerate().skip(1) {
+                    error = error.with_secondary(globals[*x].fi, &format!("{} depends on {}", names[i], names[i + 1]));
+                }
+                errors.push(error);
+                for x in cycle {
+                    failed[*x] = true;
+                }
             }
         }
+        path.pop();
         state[n] = 2;
         stack.push(n);
     }
 
-    fn find_global_dependencies(prog: &Program) -> Vec<Vec<usize>> {
+    /// A global whose value can not be walked has no dependencies and is marked as failed.
+    fn find_global_dependencies(prog: &Program, failed: &mut [bool], errors: &mut Vec<CheckError>) -> Vec<Vec<usize>> {
         let mut dependencies = Vec::with_capacity(prog.globals.len());
-        for global in &prog.globals {
-            dependencies.push(Self::vars_in_global_expression(
-                &global.value,
-                &prog.globals,
-            ));
+        for (i, global) in prog.globals.iter().enumerate() {
+            match Self::vars_in_global_expression(&global.value, &prog.globals) {
+                Ok(vars) => dependencies.push(vars),
+                Err(e) => {
+                    errors.push(e);
+                    failed[i] = true;
+                    dependencies.push(Vec::new());
+                }
+            }
         }
         dependencies
     }
 
-    fn vars_in_global_expression(exp: &Exp, globals: &Vec<Global>) -> Vec<usize> {
+    fn vars_in_global_expression(exp: &Exp, globals: &Vec<Global>) -> Result<Vec<usize>, CheckError> {
         match exp {
-            Exp::U64(_, _) => Vec::new(),
-            Exp::I64(_, _) => Vec::new(),
-            Exp::Var(var, _) => {
-                let index = globals.iter().position(|x| x.name == *var);
-                Vec::from([index.unwrap()])
-            }
+            Exp::U64(_, _) => Ok(Vec::new()),
+            Exp::I64(_, _) => Ok(Vec::new()),
+            Exp::U32(_, _) | Exp::I32(_, _) | Exp::U16(_, _) | Exp::I16(_, _) | Exp::U8(_, _) | Exp::I8(_, _) => Ok(Vec::new()),
+            Exp::F64(_, _) => Ok(Vec::new()),
+            Exp::Bool(_, _) => Ok(Vec::new()),
+            Exp::Str(_, _) => Ok(Vec::new()),
+            Exp::Var(var, fi) => match globals.iter().position(|x| x.name == *var) {
+                Some(index) => Ok(Vec::from([index])),
+                None => Err(Diagnostic::new(&format!("Global {} not found, globals can only use other globals", var), *fi)),
+            },
             Exp::BinOp(e1, _op, e2, _) => {
-                let mut vars = Self::vars_in_global_expression(e1, globals);
-                vars.append(&mut Self::vars_in_global_expression(e2, globals));
-                vars
+                let mut vars = Self::vars_in_global_expression(e1, globals)?;
+                vars.append(&mut Self::vars_in_global_expression(e2, globals)?);
+                Ok(vars)
+            }
+            Exp::UnaryOp(_, e, _) | Exp::Field(e, _, _) | Exp::Cast(e, _, _) => {
+                Self::vars_in_global_expression(e, globals)
+            }
+            Exp::Index(array, index, _) => {
+                let mut vars = Self::vars_in_global_expression(array, globals)?;
+                vars.append(&mut Self::vars_in_global_expression(index, globals)?);
+                Ok(vars)
             }
-            Exp::Call(_call) => {
-                panic!("Function calls not allowed in global expressions");
+            Exp::Array(elements, _) | Exp::Variant(_, _, elements, _) => {
+                let mut vars = Vec::new();
+                for e in elements {
+                    vars.append(&mut Self::vars_in_global_expression(e, globals)?);
+                }
+                Ok(vars)
+            }
+            Exp::Struct(_, fields, _) => {
+                let mut vars = Vec::new();
+                for (_, e) in fields {
+                    vars.append(&mut Self::vars_in_global_expression(e, globals)?);
+                }
+                Ok(vars)
             }
+            Exp::Call(call) => Err(Diagnostic::new("Function calls not allowed in global expressions", call.fi)),
         }
     }
 
-    fn check_function(function: &Function, prog_env: &ProgEnv) -> Result<FuncEnv, CheckError> {
+    fn check_function(function: &Function, prog_env: &ProgEnv) -> Result<FuncEnv, Vec<CheckError>> {
         let mut function_params = Vec::new();
-        let mut local_variables = Vec::new();
-
         for param in &function.params {
             let p_var = Variable {
                 name: param.name.clone(),
@@ -216,122 +759,284 @@ impl CheckedProgram {
                 .iter()
                 .any(|x: &Variable| x.name == param.name)
             {
-                return Err(
-                    CheckError::new(&format!("Duplicate parameter name {} in function {}", param.name, function.name), param.fi)
-                );
+                return Err(vec![
+                    Diagnostic::new(&format!("Duplicate parameter name {} in function {}", param.name, function.name), param.fi)
+                ]);
+            }
+            // strings are small enough to be copied, arrays are not
+            if matches!(param.ttype, Type_::Array(_, _, _)) {
+                return Err(vec![
+                    Diagnostic::new(&format!("Parameter {} can not be an array, pass a pointer instead", param.name), param.fi)
+                ]);
             }
             function_params.push(p_var);
         }
-
-        for statement in &function.body {
-            match statement {
-                Stmt::Let(let_stmt) => {
-                    let var = Variable {
-                        name: let_stmt.name.clone(),
-                        ttype: let_stmt.ttype.clone(),
-                    };
-                    if local_variables
-                        .iter()
-                        .any(|x: &Variable| x.name == let_stmt.name)
-                    {
-                        return Err(
-                            CheckError::new(&format!("Duplicate variable name {} in function {}", let_stmt.name, function.name), let_stmt.fi)
-                        );
-                    }
-                    local_variables.push(var);
-                }
-                _ => {}
-            }
+        for param in &function.params {
+            Self::check_type(&param.ttype, &prog_env.types).map_err(|e| vec![e])?;
+        }
+        Self::check_type(&function.ret_type, &prog_env.types).map_err(|e| vec![e])?;
+        if function.ret_type.is_aggregate() {
+            return Err(vec![
+                Diagnostic::new(&format!("Function {} can not return `{}`, return a pointer instead", function.name, function.ret_type), function.ret_type.fi())
+            ]);
         }
 
-        let function_env = FuncEnv {
+        let mut function_env = FuncEnv {
+            param_sizes: function_params.iter().map(|x| prog_env.size_of(&x.ttype)).collect(),
             function_params,
-            local_variables,
+            local_variables: Vec::new(),
+            local_sizes: Vec::new(),
+            local_fis: Vec::new(),
             ret_type: function.ret_type.clone(),
+            scopes: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
         };
 
-        Self::check_statements(&function.body, &function_env, &prog_env)?;
+        Self::check_block(&function.body, &mut function_env, prog_env, false);
+        if !function_env.errors.is_empty() {
+            function_env.errors.append(&mut function_env.warnings);
+            return Err(std::mem::take(&mut function_env.errors));
+        }
 
         Ok(function_env)
     }
 
-    fn check_statement(stmt: &Stmt, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<(), CheckError> {
+    fn check_statement(stmt: &Stmt, f_env: &mut FuncEnv, p_env: &ProgEnv, in_loop: bool) -> Result<(), CheckError> {
         match stmt {
             Stmt::If(if_stmt) => {
-                // Top level of the condition must be a comparison
-                Self::check_comparison_operator(&if_stmt.condition)?;
-                Self::check_expression(&if_stmt.condition, f_env, p_env)?;
-                Self::check_statements(&if_stmt.body, f_env, p_env)?;
-                Self::check_statements(&if_stmt.else_body, f_env, p_env)?;
+                Self::check_condition(&if_stmt.condition, f_env, p_env)?;
+                Self::check_block(&if_stmt.body, f_env, p_env, in_loop);
+                Self::check_block(&if_stmt.else_body, f_env, p_env, in_loop);
             }
             Stmt::While(while_stmt) => {
-                Self::check_comparison_operator(&while_stmt.condition)?;
-                Self::check_expression(&while_stmt.condition, f_env, p_env)?;
-                Self::check_statements(&while_stmt.body, f_env, p_env)?;
+                Self::check_condition(&while_stmt.condition, f_env, p_env)?;
+                Self::check_block(&while_stmt.body, f_env, p_env, true);
             }
             Stmt::DoWhile(do_while_stmt) => {
-                Self::check_comparison_operator(&do_while_stmt.condition)?;
-                Self::check_expression(&do_while_stmt.condition, f_env, p_env)?;
-                Self::check_statements(&do_while_stmt.body, f_env, p_env)?;
+                Self::check_block(&do_while_stmt.body, f_env, p_env, true);
+                Self::check_condition(&do_while_stmt.condition, f_env, p_env)?;
+            }
+            Stmt::For(for_stmt) => {
+                // the loop variable lives in its own scope around the loop
+                f_env.enter_scope();
+                let checked = Self::check_for(for_stmt, f_env, p_env);
+                f_env.exit_scope();
+                checked?;
             }
             Stmt::Let(let_stmt) => {
-                let exp_type = Self::check_expression(&let_stmt.value, f_env, p_env)?;
-                if exp_type.neq(&let_stmt.ttype) {
-                    return Err(
-                        CheckError::new(&format!("Type mismatch in let statement: {:?} and {:?}", exp_type, let_stmt.ttype), let_stmt.fi)
-                    );
-                }
+                Self::check_type(&let_stmt.ttype, &p_env.types)?;
+                // declared even with a wrong value, so the statements using it are still checked
+                let checked = Self::check_let(let_stmt, f_env, p_env);
+                f_env.declare_local(&let_stmt.name, &let_stmt.ttype, let_stmt.fi, p_env)?;
+                checked?;
             }
+            Stmt::Match(match_stmt) => Self::check_match(match_stmt, f_env, p_env, in_loop)?,
             Stmt::Asm(_) => {} // No checks, programer is responsible for writing correct assembly
             Stmt::Return(return_stmt) => {
                 let exp_type = Self::check_expression(&return_stmt.value, f_env, p_env)?;
                 if exp_type.neq(&f_env.ret_type) {
                     return Err(
-                        CheckError::new(&format!("Type mismatch in return statement: {:?} and {:?}", exp_type, f_env.ret_type), return_stmt.fi)
+                        Self::mismatch("return statement", &return_stmt.value, &exp_type, &f_env.ret_type)
+                            .with_secondary(f_env.ret_type.fi(), "expected because of the return type")
                     );
                 }
             }
-            Stmt::Assign(assign_stmt) => {
-                let exp_type = Self::check_expression(&assign_stmt.value, f_env, p_env)?;
-                let var = p_env
-                    .get_var(&assign_stmt.name, f_env)
-                    .ok_or(
-                        CheckError::new(&format!("Variable {} not found", assign_stmt.name), assign_stmt.fi)
-                    )?;
-                if exp_type.neq(&var.ttype) {
+            Stmt::Assign(assign_stmt) => Self::check_assign(assign_stmt, f_env, p_env)?,
+            Stmt::Call(call) => {
+                Self::check_call(call, f_env, p_env)?;
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => {
+                if !in_loop {
                     return Err(
-                        CheckError::new(&format!("Type mismatch in assignment: {:?} and {:?}", exp_type, var.ttype), assign_stmt.fi)
+                        Diagnostic::new("break and continue can only be used inside a loop", stmt.fi())
                     );
                 }
             }
-            Stmt::Call(call) => {
-                Self::check_call(call, f_env, p_env)?;
-            }
         }
         Ok(())
     }
 
-    fn check_comparison_operator(exp: &Exp) -> Result<(), CheckError> {
-        if let Exp::BinOp(_, op, _, _) = exp {
-            match op {
-                Op::Eq | Op::Ne | Op::LT | Op::GT => return Ok(()),
-                _ => return Err(
-                    CheckError::new(&format!("Invalid comparison expression: {:?}", op), exp.fi())
-                ),
+    fn check_for(for_stmt: &For, f_env: &mut FuncEnv, p_env: &ProgEnv) -> Result<(), CheckError> {
+        Self::check_let(&for_stmt.init, f_env, p_env)?;
+        f_env.declare_local(&for_stmt.init.name, &for_stmt.init.ttype, for_stmt.init.fi, p_env)?;
+        Self::check_condition(&for_stmt.condition, f_env, p_env)?;
+        Self::check_assign(&for_stmt.step, f_env, p_env)?;
+        Self::check_block(&for_stmt.body, f_env, p_env, true);
+        Ok(())
+    }
+
+    fn check_match(match_stmt: &Match, f_env: &mut FuncEnv, p_env: &ProgEnv, in_loop: bool) -> Result<(), CheckError> {
+        let value = &match_stmt.value;
+        let ttype = Self::check_expression(value, f_env, p_env)?;
+        let def = match &ttype {
+            Type_::Named(name, _) => p_env.get_enum(name),
+            _ => None,
+        }
+        .ok_or(Diagnostic::new(&format!("Can only match on enums, got `{}`", ttype), value.fi()))?;
+        // the arms read the tag and the payload from memory
+        if !value.is_place() {
+            return Err(
+                Diagnostic::new("Can only match on enums stored in variables", value.fi())
+            );
+        }
+
+        let mut covered: Vec<&str> = Vec::new();
+        for (i, arm) in match_stmt.arms.iter().enumerate() {
+            let payload: &[FieldDef] = match &arm.variant {
+                None if i + 1 != match_stmt.arms.len() => {
+                    return Err(Diagnostic::new("The _ arm must be the last one", arm.fi));
+                }
+                None => &[],
+                Some(name) => {
+                    let variant = def.get_variant(name).ok_or(
+                        Diagnostic::new(&format!("Enum {} has no variant {}", def.name, name), arm.fi)
+                    )?;
+                    if covered.contains(&name.as_str()) {
+                        return Err(
+                            Diagnostic::new(&format!("Variant {} is matched more than once", name), arm.fi)
+                        );
+                    }
+                    covered.push(name);
+                    if arm.bindings.len() != variant.payload.len() {
+                        return Err(
+                            Diagnostic::new(&format!("Variant {}::{} holds {} values, {} bindings given", def.name, name, variant.payload.len(), arm.bindings.len()), arm.fi)
+                        );
+                    }
+                    &variant.payload
+                }
+            };
+
+            // the bindings are locals of the arm's block, declared before its statements
+            f_env.enter_block(&arm.body);
+            for (binding, field) in arm.bindings.iter().zip(payload) {
+                if binding != "_" {
+                    if let Err(e) = f_env.declare_local(binding, &field.ttype, arm.fi, p_env) {
+                        f_env.errors.push(e);
+                    }
+                }
             }
+            Self::check_statements(&arm.body, f_env, p_env, in_loop);
+            f_env.exit_scope();
+        }
+
+        let has_wildcard = matches!(match_stmt.arms.last(), Some(MatchArm { variant: None, .. }));
+        let missing: Vec<&str> = def
+            .variants
+            .iter()
+            .map(|x| x.name.as_str())
+            .filter(|x| !covered.contains(x))
+            .collect();
+        if !has_wildcard && !missing.is_empty() {
+            return Err(
+                Diagnostic::new(&format!("Match on {} does not cover {}", def.name, missing.join(", ")), match_stmt.fi)
+                    .with_help(&format!("add an arm for {} or a `_ => {{}}` arm at the end", missing.join(", ")))
+            );
         }
-        Err(
-            CheckError::new(&format!("Invalid comparison expression: {:?}", exp), exp.fi())
+        Ok(())
+    }
+
+    fn check_let(let_stmt: &Let, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<(), CheckError> {
+        Self::check_type(&let_stmt.ttype, &p_env.types)?;
+        let exp_type = Self::check_expression(&let_stmt.value, f_env, p_env)?;
+        if exp_type.neq(&let_stmt.ttype) {
+            return Err(
+                Self::mismatch("let statement", &let_stmt.value, &exp_type, &let_stmt.ttype)
+                    .with_secondary(let_stmt.ttype.fi(), "expected because of this type")
+            );
+        }
+        Ok(())
+    }
+
+    /// `value` has `found` where `expected` was needed, the caller adds where the expectation comes from.
+    fn mismatch(context: &str, value: &Exp, found: &Type_, expected: &Type_) -> CheckError {
+        let error = Diagnostic::new(
+            &format!("Type mismatch in {}: expected `{}`, found `{}`", context, expected, found),
+            value.fi(),
         )
+        .with_label(&format!("this is `{}`", found));
+        match Self::literal_help(value, expected) {
+            Some(help) => error.with_help(&help),
+            None => error,
+        }
     }
 
-    fn check_statements(stmts: &Vec<Stmt>, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<(), CheckError> {
-        for statement in stmts {
-            Self::check_statement(statement, f_env, p_env)?;
+    /// Literals without a suffix are `i64`, suggests the suffix for the type that was expected,
+    /// or a cast when the literal does not fit in it.
+    fn literal_help(value: &Exp, expected: &Type_) -> Option<String> {
+        let Exp::I64(n, _) = value else {
+            return None;
+        };
+        if expected.is_integer() && Self::wrap_to_type(*n, expected) != *n {
+            Some(format!("`{}` does not fit in `{}`, write `{} as {}` to wrap it around", n, expected, n, expected))
+        } else if expected.is_integer() {
+            Some(format!("write the literal as `{}{}`", n, expected))
+        } else if expected.is_float() {
+            Some(format!("write the literal as `{}.0`", n))
+        } else {
+            None
+        }
+    }
+
+    fn unknown_variable(name: &str, fi: FI, f_env: &FuncEnv) -> CheckError {
+        if f_env.declared_later(name) {
+            Diagnostic::new(&format!("Variable {} used before its declaration", name), fi)
+        } else {
+            Diagnostic::new(&format!("Variable {} not found", name), fi)
+        }
+    }
+
+    fn check_assign(assign_stmt: &Assign, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<(), CheckError> {
+        let exp_type = Self::check_expression(&assign_stmt.value, f_env, p_env)?;
+        if !assign_stmt.target.is_place() {
+            return Err(
+                Diagnostic::new("Can only assign to a variable or a dereference", assign_stmt.target.fi())
+            );
+        }
+        let target_type = Self::check_expression(&assign_stmt.target, f_env, p_env)?;
+        if exp_type.neq(&target_type) {
+            return Err(
+                Self::mismatch("assignment", &assign_stmt.value, &exp_type, &target_type)
+                    .with_secondary(assign_stmt.target.fi(), &format!("this is `{}`", target_type))
+            );
+        }
+        Ok(())
+    }
+
+    fn check_condition(exp: &Exp, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<(), CheckError> {
+        let ttype = Self::check_expression(exp, f_env, p_env)?;
+        if ttype.neq(&Type_::Bool(FI::zero())) {
+            return Err(
+                Diagnostic::new(&format!("Condition must be of type bool, got `{}`", ttype), exp.fi())
+            );
         }
         Ok(())
     }
 
+    fn check_block(stmts: &[Stmt], f_env: &mut FuncEnv, p_env: &ProgEnv, in_loop: bool) {
+        f_env.enter_block(stmts);
+        Self::check_statements(stmts, f_env, p_env, in_loop);
+        f_env.exit_scope();
+    }
+
+    /// A failing statement records its error in `f_env`, the following ones are still checked.
+    fn check_statements(stmts: &[Stmt], f_env: &mut FuncEnv, p_env: &ProgEnv, in_loop: bool) {
+        for statement in stmts {
+            if let Err(e) = Self::check_statement(statement, f_env, p_env, in_loop) {
+                f_env.errors.push(e);
+            }
+        }
+        // code after a jump out of the block never runs, warned once per block
+        let jump = stmts.iter().position(|x| matches!(x, Stmt::Return(_) | Stmt::Break(_) | Stmt::Continue(_)));
+        if let Some(next) = jump.and_then(|i| stmts.get(i + 1)) {
+            let jump = &stmts[jump.unwrap()];
+            f_env.warnings.push(
+                Diagnostic::warning("Unreachable statement", next.fi())
+                    .with_secondary(jump.fi(), "any code after this is unreachable")
+            );
+        }
+    }
+
     pub fn get_type(exp: &Exp, f_env: &FuncEnv, p_env: &ProgEnv) -> Type_ {
         Self::check_expression(exp, f_env, p_env).unwrap()
     }
@@ -344,39 +1049,261 @@ impl CheckedProgram {
         let ttype = match exp {
             Exp::U64(_, fi) => Type_::U64(*fi),
             Exp::I64(_, fi) => Type_::I64(*fi),
+            Exp::U32(_, fi) => Type_::U32(*fi),
+            Exp::I32(_, fi) => Type_::I32(*fi),
+            Exp::U16(_, fi) => Type_::U16(*fi),
+            Exp::I16(_, fi) => Type_::I16(*fi),
+            Exp::U8(_, fi) => Type_::U8(*fi),
+            Exp::I8(_, fi) => Type_::I8(*fi),
+            Exp::F64(_, fi) => Type_::F64(*fi),
+            Exp::Bool(_, fi) => Type_::Bool(*fi),
+            Exp::Str(_, fi) => Type_::Str(*fi),
             Exp::Var(variable, _) => {
                 p_env
                     .get_var(variable, f_env)
-                    .ok_or(
-                        CheckError::new(&format!("Variable {} not found", variable), exp.fi())
-                    )?
+                    .ok_or_else(|| Self::unknown_variable(variable, exp.fi(), f_env))?
                     .ttype
+                    .clone()
             }
-            Exp::BinOp(e1, _op, e2, _) => {
-                let ltype = Self::check_expression(&e1, f_env, p_env)?;
-                let rtype = Self::check_expression(&e2, f_env, p_env)?;
+            Exp::BinOp(e1, op, e2, fi) => {
+                let ltype = Self::check_expression(e1, f_env, p_env)?;
+                let rtype = Self::check_expression(e2, f_env, p_env)?;
+                // pointer arithmetic, the offset counts elements of the pointed-to type
+                if ltype.is_pointer() && rtype.is_integer() && matches!(op, Op::Add | Op::Sub) {
+                    return Ok(ltype);
+                }
                 if ltype.neq(&rtype) {
+                    let mut error = Diagnostic::new(&format!("Type mismatch in binary operation: `{}` and `{}`", ltype, rtype), exp.fi())
+                        .with_secondary(e1.fi(), &format!("this is `{}`", ltype))
+                        .with_secondary(e2.fi(), &format!("this is `{}`", rtype));
+                    if let Some(help) = Self::literal_help(e1, &rtype).or_else(|| Self::literal_help(e2, &ltype)) {
+                        error = error.with_help(&help);
+                    }
+                    return Err(error);
+                }
+                if ltype.is_aggregate() {
                     return Err(
-                        CheckError::new(&format!("Type mismatch in binary operation: {:?} and {:?}", ltype, rtype), exp.fi())
+                        Diagnostic::new(&format!("Operator {:?} can not be applied to `{}`", op, ltype), exp.fi())
                     );
                 }
-                ltype
+                match op {
+                    Op::Eq | Op::Ne => Type_::Bool(*fi),
+                    Op::And | Op::Or => {
+                        if ltype.neq(&Type_::Bool(FI::zero())) {
+                            return Err(
+                                Diagnostic::new(&format!("Operator {:?} expects bool operands, got `{}`", op, ltype), exp.fi())
+                            );
+                        }
+                        Type_::Bool(*fi)
+                    }
+                    // distance between two pointers, in elements
+                    Op::Sub if ltype.is_pointer() => Type_::I64(*fi),
+                    Op::LT | Op::GT | Op::LE | Op::GE if ltype.is_pointer() => Type_::Bool(*fi),
+                    Op::LT | Op::GT | Op::LE | Op::GE if ltype.is_float() => Type_::Bool(*fi),
+                    Op::Add | Op::Sub | Op::Mul | Op::Div if ltype.is_float() => ltype,
+                    _ if !ltype.is_integer() => {
+                        return Err(
+                            Diagnostic::new(&format!("Operator {:?} expects integer operands, got `{}`", op, ltype), exp.fi())
+                        );
+                    }
+                    Op::LT | Op::GT | Op::LE | Op::GE => Type_::Bool(*fi),
+                    _ => ltype,
+                }
             }
-            Exp::Call(call) => Self::check_call(call, f_env, p_env)?,
-        };
-        Ok(ttype)
-    }
-
+            Exp::UnaryOp(op, e, fi) => {
+                let etype = Self::check_expression(e, f_env, p_env)?;
+                match op {
+                    UnOp::Not => {
+                        if etype.neq(&Type_::Bool(FI::zero())) {
+                            return Err(
+                                Diagnostic::new(&format!("Operator {:?} expects a bool operand, got `{}`", op, etype), exp.fi())
+                            );
+                        }
+                        Type_::Bool(*fi)
+                    }
+                    UnOp::BitNot => {
+                        if !etype.is_integer() {
+                            return Err(
+                                Diagnostic::new(&format!("Operator {:?} expects an integer operand, got `{}`", op, etype), exp.fi())
+                            );
+                        }
+                        etype
+                    }
+                    UnOp::Neg => {
+                        if !etype.is_signed() && !etype.is_float() {
+                            return Err(
+                                Diagnostic::new(&format!("Operator {:?} expects a signed operand, got `{}`", op, etype), exp.fi())
+                            );
+                        }
+                        etype
+                    }
+                    UnOp::Deref => match etype {
+                        Type_::Ptr(inner, _) => *inner,
+                        _ => {
+                            return Err(
+                                Diagnostic::new(&format!("Operator {:?} expects a pointer operand, got `{}`", op, etype), exp.fi())
+                            );
+                        }
+                    },
+                    UnOp::AddrOf => {
+                        if !e.is_place() {
+                            return Err(
+                                Diagnostic::new("Can only take the address of a variable or a dereference", exp.fi())
+                            );
+                        }
+                        Type_::Ptr(Box::new(etype), *fi)
+                    }
+                }
+            }
+            Exp::Index(array, index, _) => {
+                let array_type = Self::check_expression(array, f_env, p_env)?;
+                let Type_::Array(elem, _, _) = array_type else {
+                    return Err(
+                        Diagnostic::new(&format!("Only arrays can be indexed, got `{}`", array_type), array.fi())
+                    );
+                };
+                // the array has to live in memory, literals are only allowed as whole values
+                if !array.is_place() {
+                    return Err(
+                        Diagnostic::new("Can only index arrays stored in variables", array.fi())
+                    );
+                }
+                let index_type = Self::check_expression(index, f_env, p_env)?;
+                if !index_type.is_integer() {
+                    return Err(
+                        Diagnostic::new(&format!("Array index must be an integer, got `{}`", index_type), index.fi())
+                    );
+                }
+                *elem
+            }
+            Exp::Array(elements, fi) => {
+                let Some(first) = elements.first() else {
+                    return Err(Diagnostic::new("Array literal can not be empty", *fi));
+                };
+                let elem_type = Self::check_expression(first, f_env, p_env)?;
+                for element in &elements[1..] {
+                    let ttype = Self::check_expression(element, f_env, p_env)?;
+                    if ttype.neq(&elem_type) {
+                        return Err(
+                            Diagnostic::new(&format!("Type mismatch in array literal: `{}` and `{}`", elem_type, ttype), element.fi())
+                        );
+                    }
+                }
+                Type_::Array(Box::new(elem_type), elements.len() as u64, *fi)
+            }
+            Exp::Struct(name, fields, fi) => {
+                let def = p_env
+                    .get_struct(name)
+                    .ok_or(Diagnostic::new(&format!("Unknown struct {}", name), exp.fi()))?;
+                for (i, (field_name, value)) in fields.iter().enumerate() {
+                    if fields[..i].iter().any(|(n, _)| n == field_name) {
+                        return Err(
+                            Diagnostic::new(&format!("Field {} given twice in literal of struct {}", field_name, name), value.fi())
+                        );
+                    }
+                    let field = def.get_field(field_name).ok_or(
+                        Diagnostic::new(&format!("Struct {} has no field {}", name, field_name), value.fi())
+                    )?;
+                    let value_type = Self::check_expression(value, f_env, p_env)?;
+                    if value_type.neq(&field.ttype) {
+                        return Err(
+                            Diagnostic::new(&format!("Field {} of struct {} expects `{}`, got `{}`", field_name, name, field.ttype, value_type), value.fi())
+                        );
+                    }
+                }
+                if let Some(missing) = def.fields.iter().find(|f| !fields.iter().any(|(n, _)| *n == f.name)) {
+                    return Err(
+                        Diagnostic::new(&format!("Missing field {} in literal of struct {}", missing.name, name), exp.fi())
+                    );
+                }
+                Type_::Named(name.clone(), *fi)
+            }
+            Exp::Variant(name, variant_name, payload, fi) => {
+                let def = p_env
+                    .get_enum(name)
+                    .ok_or(Diagnostic::new(&format!("Unknown enum {}", name), exp.fi()))?;
+                let variant = def.get_variant(variant_name).ok_or(
+                    Diagnostic::new(&format!("Enum {} has no variant {}", name, variant_name), exp.fi())
+                )?;
+                if payload.len() != variant.payload.len() {
+                    return Err(
+                        Diagnostic::new(&format!("Variant {}::{} holds {} values, {} given", name, variant_name, variant.payload.len(), payload.len()), exp.fi())
+                    );
+                }
+                for (value, field) in payload.iter().zip(&variant.payload) {
+                    let value_type = Self::check_expression(value, f_env, p_env)?;
+                    if value_type.neq(&field.ttype) {
+                        return Err(
+                            Diagnostic::new(&format!("Variant {}::{} expects `{}`, got `{}`", name, variant_name, field.ttype, value_type), value.fi())
+                        );
+                    }
+                }
+                Type_::Named(name.clone(), *fi)
+            }
+            Exp::Field(base, name, fi) => {
+                // struct literals only exist as stored values, they have no address to read from
+                if matches!(**base, Exp::Struct(_, _, _)) {
+                    return Err(
+                        Diagnostic::new("Fields of a struct literal can not be read, store it in a variable first", exp.fi())
+                    );
+                }
+                let base_type = Self::check_expression(base, f_env, p_env)?;
+                match (&base_type, name.as_str()) {
+                    (Type_::Str(_), "ptr") => Type_::Ptr(Box::new(Type_::U8(*fi)), *fi),
+                    (Type_::Str(_), "len") => Type_::U64(*fi),
+                    (Type_::Named(enum_name, _), _) if p_env.get_enum(enum_name).is_some() => {
+                        return Err(
+                            Diagnostic::new(&format!("Enum {} has no fields, use match to read its payload", enum_name), exp.fi())
+                        );
+                    }
+                    (Type_::Named(struct_name, _), _) => match p_env.get_struct(struct_name).and_then(|def| def.get_field(name)) {
+                        Some(field) => field.ttype.clone(),
+                        None => {
+                            return Err(
+                                Diagnostic::new(&format!("Struct {} has no field {}", struct_name, name), exp.fi())
+                            );
+                        }
+                    },
+                    _ => {
+                        return Err(
+                            Diagnostic::new(&format!("Type `{}` has no field {}", base_type, name), exp.fi())
+                        );
+                    }
+                }
+            }
+            Exp::Cast(e, target, _) => {
+                Self::check_type(target, &p_env.types)?;
+                let from = Self::check_expression(e, f_env, p_env)?;
+                let u64_type = Type_::U64(FI::zero());
+                // numbers convert between each other, pointers only to and from u64 addresses
+                let is_number = |t: &Type_| t.is_integer() || t.is_float();
+                let allowed = ((is_number(&from) || from.eq(&Type_::Bool(FI::zero()))) && is_number(target))
+                    || (from.is_pointer() && (target.is_pointer() || target.eq(&u64_type)))
+                    || (from.eq(&u64_type) && target.is_pointer());
+                if !allowed {
+                    return Err(
+                        Diagnostic::new(&format!("Can not cast `{}` to `{}`", from, target), exp.fi())
+                    );
+                }
+                target.clone()
+            }
+            Exp::Call(call) => Self::check_call(call, f_env, p_env)?,
+        };
+        Ok(ttype)
+    }
+
     fn check_call(call: &Call, f_env: &FuncEnv, p_env: &ProgEnv) -> Result<Type_, CheckError> {
         let fn_sig = p_env
             .get_signature(&call.name)
             .ok_or(
-                CheckError::new(&format!("Function {} not found", call.name), call.fi)
+                Diagnostic::new(&format!("Function {} not found", call.name), call.fi)
             )?;
 
+        let declared_here = format!("function `{}` declared here", call.name);
         if fn_sig.params.len() != call.args.len() {
             return Err(
-                CheckError::new(&format!("Function {} takes {} parameters, {} given", call.name, fn_sig.params.len(), call.args.len()), call.fi)
+                Diagnostic::new(&format!("Function {} takes {} parameters, {} given", call.name, fn_sig.params.len(), call.args.len()), call.fi)
+                    .with_secondary(fn_sig.fi, &declared_here)
                 );
         }
 
@@ -384,12 +1311,13 @@ impl CheckedProgram {
             let exp_type = Self::check_expression(arg, f_env, p_env)?;
             if exp_type.neq(&fn_sig.params[i]) {
                 return Err(
-                    CheckError::new(&format!("Type mismatch in function call: {:?} and {:?}", exp_type, fn_sig.params[i]), arg.fi())
+                    Self::mismatch("function call", arg, &exp_type, &fn_sig.params[i])
+                        .with_secondary(fn_sig.fi, &declared_here)
                 );
             }
         }
 
-        Ok(fn_sig.ret_type)
+        Ok(fn_sig.ret_type.clone())
     }
 }
 
@@ -408,38 +1336,204 @@ impl ProgEnv {
         }
         self.get_global_def(name)
     }
+
+    pub fn get_struct(&self, name: &str) -> Option<&StructDef> {
+        self.types.get_struct(name)
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&EnumDef> {
+        self.types.get_enum(name)
+    }
+
+    /// Size in bytes of a value of this type, including structs and enums.
+    pub fn size_of(&self, ttype: &Type_) -> usize {
+        size_of(ttype, &self.types)
+    }
+}
+
+impl TypeDefs {
+    pub fn get_struct(&self, name: &str) -> Option<&StructDef> {
+        self.structs.iter().find(|x| x.name == name)
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&EnumDef> {
+        self.enums.iter().find(|x| x.name == name)
+    }
+}
+
+impl StructDef {
+    pub fn get_field(&self, name: &str) -> Option<&FieldDef> {
+        self.fields.iter().find(|x| x.name == name)
+    }
+}
+
+impl EnumDef {
+    pub fn get_variant(&self, name: &str) -> Option<&VariantDef> {
+        self.variants.iter().find(|x| x.name == name)
+    }
+}
+
+fn get_struct<'a>(name: &str, types: &'a TypeDefs) -> &'a StructDef {
+    types
+        .get_struct(name)
+        .unwrap_or_else(|| panic!("struct {} is not laid out", name))
+}
+
+fn size_of(ttype: &Type_, types: &TypeDefs) -> usize {
+    match ttype {
+        Type_::Named(name, _) => match types.get_enum(name) {
+            Some(def) => def.size,
+            None => get_struct(name, types).size,
+        },
+        Type_::Array(elem, len, _) => size_of(elem, types) * *len as usize,
+        _ => ttype.size(),
+    }
+}
+
+/// Scalars are aligned to their size, aggregates to their most aligned part.
+fn align_of(ttype: &Type_, types: &TypeDefs) -> usize {
+    match ttype {
+        Type_::Named(name, _) => match types.get_enum(name) {
+            Some(def) => def.align,
+            None => get_struct(name, types).align,
+        },
+        Type_::Array(elem, _, _) => align_of(elem, types),
+        Type_::Str(_) => 8,
+        _ => ttype.size(),
+    }
 }
 
 impl FuncEnv {
     fn get_var(&self, name: &str) -> Option<&Variable> {
-        if let Some(var) = self.function_params.iter().find(|x| x.name == name) {
-            return Some(var);
+        if let Some(pos) = self.get_local_pos(name) {
+            return Some(&self.local_variables[pos]);
         }
-        if let Some(var) = self.local_variables.iter().find(|x| x.name == name) {
-            return Some(var);
+        self.function_params.iter().find(|x| x.name == name)
+    }
+
+    /// Opens a block, its lets are declared one by one as they are reached.
+    /// The code generator opens the blocks again to resolve names, it binds the slots
+    /// the checker declared instead of declaring them again.
+    pub fn enter_block(&mut self, stmts: &[Stmt]) {
+        let upcoming = stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Let(let_stmt) => Some(let_stmt.name.clone()),
+                _ => None,
+            })
+            .collect();
+        self.scopes.push(Scope { locals: Vec::new(), upcoming });
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    pub fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Lets, for loop variables and match bindings, `fi` is where the error goes for a duplicate name.
+    pub fn declare_local(&mut self, name: &str, ttype: &Type_, fi: FI, p_env: &ProgEnv) -> Result<(), CheckError> {
+        let scope = self.scopes.last().expect("declaration outside of a block");
+        if scope.locals.iter().any(|&pos| self.local_variables[pos].name == name) {
+            return Err(
+                Diagnostic::new(&format!("Duplicate variable name {} in the same scope", name), fi)
+            );
+        }
+        let pos = self.local_variables.len();
+        self.local_variables.push(Variable {
+            name: name.to_string(),
+            ttype: ttype.clone(),
+        });
+        self.local_sizes.push(p_env.size_of(ttype));
+        self.local_fis.push(fi);
+        let scope = self.scopes.last_mut().unwrap();
+        scope.locals.push(pos);
+        if let Some(i) = scope.upcoming.iter().position(|x| *x == name) {
+            scope.upcoming.remove(i);
+        }
+        Ok(())
+    }
+
+    fn declared_later(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.upcoming.iter().any(|x| x == name))
+    }
+
+    /// The slot the checker gave to the declaration of `name` at `fi`.
+    pub fn local_slot(&self, name: &str, fi: FI) -> usize {
+        self.local_variables
+            .iter()
+            .zip(&self.local_fis)
+            .position(|(var, var_fi)| var.name == name && *var_fi == fi)
+            .expect("local declared by the checker")
+    }
+
+    /// Brings a checked local into scope, for the code generator.
+    pub fn bind_local(&mut self, pos: usize) {
+        self.scopes.last_mut().expect("declaration outside of a block").locals.push(pos);
+    }
+
+    /// Distance below rbp of the start of a local, every slot is padded to 8 bytes.
+    pub fn local_offset(&self, pos: usize) -> usize {
+        self.local_sizes[..=pos]
+            .iter()
+            .map(|size| size.next_multiple_of(8))
+            .sum()
+    }
+
+    /// Distance from the last pushed argument to the start of a parameter, arguments are pushed in order.
+    pub fn param_offset(&self, pos: usize) -> usize {
+        self.param_sizes[pos + 1..]
+            .iter()
+            .map(|size| size.next_multiple_of(8))
+            .sum()
+    }
+
+    /// Bytes reserved on the stack for all locals of the function.
+    pub fn locals_size(&self) -> usize {
+        match self.local_variables.len() {
+            0 => 0,
+            n => self.local_offset(n - 1),
         }
-        None
+    }
+
+    /// Forgets the open scopes so the function body can be walked again.
+    pub fn rewind(&mut self) {
+        self.scopes.clear();
     }
 
     pub fn get_local_pos(&self, name: &str) -> Option<usize> {
-        return self.local_variables.iter().position(|x| x.name == name);
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.locals.iter().rev())
+            .copied()
+            .find(|&pos| self.local_variables[pos].name == name)
     }
     pub fn get_param_pos(&self, name: &str) -> Option<usize> {
-        return self.function_params.iter().position(|x| x.name == name);
+        self.function_params.iter().position(|x| x.name == name)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::file_info::SourceMap;
     use crate::parser::parse_program;
     use crate::tokenizer::tokenize;
 
-    fn check_program(input: &str) -> Result<CheckedProgram, CheckError> {
-        let ast = match parse_program(tokenize(input)) {
+    fn sources(input: &str) -> SourceMap {
+        let mut sources = SourceMap::default();
+        sources.add("test", input.to_string());
+        sources
+    }
+
+    fn check_program(input: &str) -> Result<CheckedProgram, Vec<CheckError>> {
+        let ast = match parse_program(tokenize(input, 0).unwrap()) {
             Ok(ast) => ast,
-            Err(e) => {
-                e.pretty_print(input);
+            Err(errors) => {
+                errors.iter().for_each(|e| e.diagnostic().pretty_print(&sources(input)));
                 panic!("Error in parsing");
             }
         };
@@ -450,7 +1544,7 @@ mod test {
     #[test]
     fn test_check_program_errors() {
         assert_some_error(
-            "Duplicate variable name in function",
+            "Duplicate variable name in the same scope",
             r#"
         fn main() -> u64 {
             let a: u64 = 5;
@@ -533,17 +1627,1022 @@ mod test {
         );
     }
 
-    fn assert_some_error(msg: &str, input: &str) {
-        let got = check_program(input);
-        match got {
-            Ok(_) => {
-                panic!(
-                    "Expected error: {}\n\ngot program:\n{:#?}",
-                    msg,
-                    got.unwrap()
-                );
+    #[test]
+    fn test_bool_type() {
+        assert_some_error(
+            "Condition must be of type bool",
+            r#"fn main() -> u64 {
+            if (5) {}
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Condition must be of type bool",
+            r#"fn main() -> u64 {
+            let a: u64 = 5;
+            while (a + 1) {}
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Operator expects integer operands",
+            r#"fn main() -> u64 {
+            let a: bool = true + false;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Operator expects integer operands",
+            r#"fn main() -> u64 {
+            let a: bool = true < false;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Type mismatch in let statement",
+            r#"fn main() -> u64 {
+            let a: u64 = 1 == 2;
+            }
+            "#,
+        );
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        assert_some_error(
+            "Operator And expects bool operands",
+            r#"fn main() -> u64 {
+            if (1 && 2) {}
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Type mismatch in binary operation",
+            r#"fn main() -> u64 {
+            if (true || 2) {}
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Operator Not expects a bool operand",
+            r#"fn main() -> u64 {
+            let a: i64 = 5;
+            if (!a) {}
+            }
+            "#,
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        assert_some_error(
+            "Operator BitAnd expects integer operands",
+            r#"fn main() -> u64 {
+            let a: bool = true & false;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Type mismatch in binary operation",
+            r#"fn main() -> u64 {
+            let a: u64 = 1u64 << 3;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Operator BitNot expects an integer operand",
+            r#"fn main() -> u64 {
+            let a: bool = ~true;
+            }
+            "#,
+        );
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        assert_some_error(
+            "Operator Neg expects a signed operand",
+            r#"fn main() -> u64 {
+            let a: u64 = -5u64;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Operator Neg expects a signed operand",
+            r#"fn main() -> u64 {
+            let a: bool = -true;
+            }
+            "#,
+        );
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop() {
+        assert_some_error(
+            "break outside of a loop",
+            r#"fn main() -> u64 {
+            break;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "continue outside of a loop",
+            r#"fn main() -> u64 {
+            if (true) {
+                continue;
+            }
             }
-            Err(_) => {}
+            "#,
+        );
+    }
+
+    #[test]
+    fn test_for_loop() {
+        assert_some_error(
+            "Type mismatch in let statement",
+            r#"fn main() -> u64 {
+            for (i: u64 = 0; i < 10u64; i = i + 1u64) {}
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Condition must be of type bool",
+            r#"fn main() -> u64 {
+            for (i: i64 = 0; i + 1; i = i + 1) {}
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Type mismatch in assignment",
+            r#"fn main() -> u64 {
+            for (i: i64 = 0; i < 10; i = true) {}
+            }
+            "#,
+        );
+    }
+
+    #[test]
+    fn test_pointers() {
+        assert_some_error(
+            "Dereference of a non pointer",
+            r#"fn main() -> u64 {
+            let a: u64 = 5u64;
+            let b: u64 = *a;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Address of a literal",
+            r#"fn main() -> u64 {
+            let p: *i64 = &5;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Pointer to the wrong type",
+            r#"fn main() -> u64 {
+            let a: i64 = 5;
+            let p: *u64 = &a;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Assignment through a pointer with the wrong type",
+            r#"fn main() -> u64 {
+            let a: i64 = 5;
+            let p: *i64 = &a;
+            *p = true;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Adding two pointers",
+            r#"fn main() -> u64 {
+            let a: i64 = 5;
+            let p: *i64 = &a + &a;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Assignment to a call",
+            r#"fn main() -> u64 {
+            main() = 5u64;
+            }
+            "#,
+        );
+
+        check_program(
+            r#"fn main() -> u64 {
+            let a: i64 = 5;
+            let p: *i64 = &a;
+            let pp: **i64 = &p;
+            **pp = *p + 1;
+            let q: *i64 = p + 2u64 - 1;
+            let d: i64 = q - p;
+            let same: bool = p <= q && p != q;
+            }
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_arrays() {
+        assert_some_error(
+            "Array literal with the wrong length",
+            r#"fn main() -> u64 {
+            let a: [i64; 3] = [1, 2];
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Array literal with mixed types",
+            r#"fn main() -> u64 {
+            let a: [i64; 2] = [1, true];
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Indexing a non array",
+            r#"fn main() -> u64 {
+            let a: i64 = 1;
+            let b: i64 = a[0];
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Index must be an integer",
+            r#"fn main() -> u64 {
+            let a: [i64; 2] = [1, 2];
+            let b: i64 = a[true];
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Indexing a literal",
+            r#"fn main() -> u64 {
+            let b: i64 = [1, 2][0];
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Comparing arrays",
+            r#"fn main() -> u64 {
+            let a: [i64; 2] = [1, 2];
+            let b: bool = a == a;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Array parameter",
+            r#"fn main() -> u64 {}
+            fn sum(a: [i64; 2]) -> i64 {}
+            "#,
+        );
+
+        assert_some_error(
+            "Array return type",
+            r#"fn main() -> u64 {}
+            fn make() -> [i64; 2] {}
+            "#,
+        );
+
+        assert_some_error(
+            "Global with the wrong number of values",
+            r#"global a: [i64; 3] = [1, 2];
+            fn main() -> u64 {}
+            "#,
+        );
+
+        let prog = check_program(
+            r#"global g: [[i64; 2]; 2] = [[1, 2], [3, 4]];
+            fn main() -> u64 {
+            let x: i64 = 1;
+            let a: [i64; 3] = [1, 2, 3];
+            let m: [[bool; 2]; 3] = [[true, false], [false, true], [true, true]];
+            a[2u64] = a[0] + g[1][x];
+            let b: bool = m[2][x];
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(prog.program_env.global_values[0], vec![1, 2, 3, 4]);
+        let f_env = &prog.function_envs[0];
+        assert_eq!(f_env.locals_size(), 8 + 24 + 48 + 8);
+        assert_eq!(f_env.local_offset(1), 32);
+    }
+
+    #[test]
+    fn test_bytes() {
+        assert_some_error(
+            "Mixing u8 and u64",
+            r#"fn main() -> u64 {
+            let c: u8 = 'a' + 1u64;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Negating a u8",
+            r#"fn main() -> u64 {
+            let c: u8 = -'a';
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "A string pointer is not a u64",
+            r#"fn main() -> u64 {
+            let p: u64 = "abc".ptr;
+            }
+            "#,
+        );
+
+        check_program(
+            r#"global letters: [u8; 2] = ['a', 'b'];
+            fn main() -> u64 {
+            let c: u8 = letters[1] - 'a' + '0';
+            let buf: [u8; 3] = [c, ~c, '\n'];
+            let p: *u8 = &buf[0];
+            *(p + 1u64) = c << '\x01';
+            let upper: bool = c >= 'A' && c <= 'Z';
+            }
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_sized_integers() {
+        assert_some_error(
+            "Mixing widths",
+            r#"fn main() -> u64 {
+            let a: u32 = 1u32 + 1u16;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Mixing signedness",
+            r#"fn main() -> u64 {
+            let a: i16 = 1i16;
+            let b: bool = a < 1u16;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Literal of the wrong width",
+            r#"fn main() -> u64 {
+            let a: i8 = 1;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Casting an integer to bool",
+            r#"fn main() -> u64 {
+            let b: bool = 1 as bool;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Casting a string",
+            r#"fn main() -> u64 {
+            let n: u64 = "abc" as u64;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Casting an i64 to a pointer",
+            r#"fn main() -> u64 {
+            let p: *u8 = 1 as *u8;
+            }
+            "#,
+        );
+
+        let err = single_error("global b: u8 = 300;\nfn main() -> u64 {}");
+        assert_eq!(err.msg, "Value 300 of global b does not fit in `u8`");
+        let err = single_error("global u: u64 = -1;\nfn main() -> u64 {}");
+        assert_eq!(err.msg, "Value -1 of global u does not fit in `u64`");
+        assert_some_error("Global array element out of range", "global a: [i8; 2] = [1, -129];\nfn main() -> u64 {}");
+
+        let prog = check_program(
+            r#"global mask: u16 = 255 as u16 << 8u16;
+            global limits: [i8; 2] = [-128, 127];
+            global min: i64 = -9223372036854775808;
+            fn main() -> u64 {
+            let m: i8 = -128i8;
+            let a: i8 = -5i8 / 2i8;
+            let b: u32 = a as u32 + 1u32;
+            let c: i64 = b as i64 * 2 + true as i64;
+            let p: *u8 = "abc".ptr;
+            let q: *u16 = (p as u64 + 1u64) as *u16;
+            let r: *i32 = q as *i32;
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(prog.program_env.global_values[2], vec![i64::MIN]);
+    }
+
+    #[test]
+    fn test_floats() {
+        assert_some_error(
+            "Mixing f64 and i64",
+            r#"fn main() -> u64 {
+            let x: f64 = 1.5 + 1;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Remainder of floats",
+            r#"fn main() -> u64 {
+            let x: f64 = 1.5 % 1.0;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Bitwise operators on floats",
+            r#"fn main() -> u64 {
+            let x: f64 = ~1.5;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Casting a float to a pointer",
+            r#"fn main() -> u64 {
+            let p: *u8 = 1.5 as *u8;
+            }
+            "#,
+        );
+
+        assert_some_error("Integer global of type f64", "global f: f64 = 1;\nfn main() -> u64 {}");
+        assert_some_error("Float global of type i64", "global i: i64 = 1.5;\nfn main() -> u64 {}");
+        assert_some_error("Integer global of type bool", "global flag: bool = 7;\nfn main() -> u64 {}");
+        assert_some_error("Float in an integer array global", "global a: [i64; 2] = [1, 2.5];\nfn main() -> u64 {}");
+        assert_some_error("Typed integer in a global of another type", "global b: u16 = 7u8;\nfn main() -> u64 {}");
+        // a global read by name is a float too, its bits are not integers
+        assert_some_error("Cast of a float global", "global f: f64 = 1.5;\nglobal i: i64 = f as i64;\nfn main() -> u64 {}");
+        assert_some_error("Arithmetic on a float global", "global f: f64 = 1.5;\nglobal h: f64 = f + f;\nfn main() -> u64 {}");
+        let prog = check_program("global f: f64 = 1.5;\nglobal n: f64 = -f;\nfn main() -> u64 {}").unwrap();
+        assert_eq!(prog.program_env.global_values[1], vec![(-1.5f64).to_bits() as i64]);
+
+        check_program(
+            r#"global half: f64 = -0.5;
+            global size: u64 = 4 * 1024;
+            global bytes: [u8; 2] = [1, -2 + 3];
+            fn main() -> u64 {
+            let x: f64 = 2f64 * half - 1.25 / 3.0;
+            let n: i32 = x as i32;
+            let y: f64 = n as f64 + 7u8 as f64;
+            let below: bool = -x < y && x != y;
+            }
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_some_error(
+            "Unknown field",
+            r#"fn main() -> u64 {
+            let n: u64 = "abc".size;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Field of a non string",
+            r#"fn main() -> u64 {
+            let a: u64 = 1u64;
+            let n: u64 = a.len;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Comparing strings",
+            r#"fn main() -> u64 {
+            let b: bool = "a" == "a";
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Returning a string",
+            r#"fn main() -> u64 {}
+            fn name() -> str {}
+            "#,
+        );
+
+        assert_some_error(
+            "Assigning a string to its length",
+            r#"fn main() -> u64 {
+            let s: str = "abc";
+            s.len = "a";
+            }
+            "#,
+        );
+
+        check_program(
+            r#"fn main() -> u64 {
+            let s: str = "abc";
+            s.len = s.len - 1u64;
+            let n: u64 = "xy".len;
+            let c: u8 = *(s.ptr + n);
+            print(s, n);
+            print("lit", 1u64);
+            }
+            fn print(s: str, n: u64) -> u64 {}
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_structs() {
+        assert_some_error(
+            "Duplicate struct name",
+            r#"struct S { a: u64 }
+            struct S { b: u64 }
+            fn main() -> u64 {}
+            "#,
+        );
+
+        assert_some_error(
+            "Duplicate field name",
+            r#"struct S { a: u64, a: u8 }
+            fn main() -> u64 {}
+            "#,
+        );
+
+        assert_some_error(
+            "Struct containing itself through another struct",
+            r#"struct A { b: B }
+            struct B { a: [A; 2] }
+            fn main() -> u64 {}
+            "#,
+        );
+
+        assert_some_error(
+            "Unknown field type",
+            r#"struct S { a: Missing }
+            fn main() -> u64 {}
+            "#,
+        );
+
+        assert_some_error(
+            "Missing field in literal",
+            r#"struct S { a: u64, b: u64 }
+            fn main() -> u64 {
+            let s: S = S { a: 1u64 };
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Extra field in literal",
+            r#"struct S { a: u64 }
+            fn main() -> u64 {
+            let s: S = S { a: 1u64, b: 2u64 };
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Wrong field type in literal",
+            r#"struct S { a: u64 }
+            fn main() -> u64 {
+            let s: S = S { a: true };
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Unknown field access",
+            r#"struct S { a: u64 }
+            fn main() -> u64 {
+            let s: S = S { a: 1u64 };
+            let n: u64 = s.b;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Returning a struct",
+            r#"struct S { a: u64 }
+            fn main() -> S {}
+            "#,
+        );
+
+        assert_some_error(
+            "Field of a struct literal",
+            r#"struct S { a: u64 }
+            fn main() -> u64 {
+            let n: u64 = S { a: 1u64 }.a;
+            }
+            "#,
+        );
+
+        let checked = check_program(
+            r#"struct S { a: u8, b: u64, c: u16 }
+            struct Small { x: u8, y: u16, z: u8 }
+            struct Node { value: i64, next: *Node, tags: [Small; 3] }
+            global origin: Small = Small { z: 3u8, x: 1u8, y: 2u16 * 4u16 };
+            fn main() -> u64 {
+            let t: Small = Small { x: 1u8, y: 2u16, z: 3u8 };
+            let n: Node = Node { value: 1, next: 0u64 as *Node, tags: [t, t, Small { z: 0u8, y: 0u16, x: 0u8 }] };
+            (*n.next).tags[2].y = origin.y + 1u16;
+            let v: i64 = first(&n, n.tags[0]);
+            }
+            fn first(n: *Node, t: Small) -> i64 {}
+            "#,
+        )
+        .unwrap();
+        let env = &checked.program_env;
+        let s = env.get_struct("S").unwrap();
+        let offsets: Vec<usize> = s.fields.iter().map(|f| f.offset).collect();
+        assert_eq!((offsets, s.size, s.align), (vec![0, 8, 16], 24, 8));
+        let small = env.get_struct("Small").unwrap();
+        let offsets: Vec<usize> = small.fields.iter().map(|f| f.offset).collect();
+        assert_eq!((offsets, small.size, small.align), (vec![0, 2, 4], 6, 2));
+        assert_eq!(env.get_struct("Node").unwrap().size, 40);
+    }
+
+    #[test]
+    fn test_enums() {
+        let shape = "enum Shape { Circle(f64), Rect(i64, i64), Empty }\n";
+        let cases = [
+            ("Duplicate variant name", "enum E { A, A(u64) }\nfn main() -> u64 {}"),
+            ("Enum without variants", "enum E { }\nfn main() -> u64 {}"),
+            ("Struct and enum with the same name", "enum E { A }\nstruct E { a: u64 }\nfn main() -> u64 {}"),
+            ("Enum containing itself", "enum List { Cons(i64, List), Nil }\nfn main() -> u64 {}"),
+            ("Enum global", "enum E { A }\nglobal e: E = E::A;\nfn main() -> u64 {}"),
+            ("Unknown variant", "fn main() -> u64 {\nlet s: Shape = Shape::Square(1);\n}"),
+            ("Missing payload", "fn main() -> u64 {\nlet s: Shape = Shape::Rect(1);\n}"),
+            ("Wrong payload type", "fn main() -> u64 {\nlet s: Shape = Shape::Circle(1);\n}"),
+            ("Field of an enum", "fn main() -> u64 {\nlet s: Shape = Shape::Empty;\nlet r: f64 = s.Circle;\n}"),
+            ("Match on a non enum", "fn main() -> u64 {\nlet n: u64 = 1u64;\nmatch (n) { _ => {} }\n}"),
+            ("Match on a literal", "fn main() -> u64 {\nmatch (Shape::Empty) { _ => {} }\n}"),
+            ("Missing variant", "fn main() -> u64 {}\nfn f(s: Shape) -> u64 {\nmatch (s) { Circle(r) => {} Empty => {} }\n}"),
+            ("Variant matched twice", "fn main() -> u64 {}\nfn f(s: Shape) -> u64 {\nmatch (s) { Empty => {} Empty => {} _ => {} }\n}"),
+            ("Wildcard before other arms", "fn main() -> u64 {}\nfn f(s: Shape) -> u64 {\nmatch (s) { _ => {} Empty => {} }\n}"),
+            ("Wrong number of bindings", "fn main() -> u64 {}\nfn f(s: Shape) -> u64 {\nmatch (s) { Rect(w) => {} _ => {} }\n}"),
+            ("Binding used outside its arm", "fn main() -> u64 {}\nfn f(s: Shape) -> u64 {\nmatch (s) { Circle(r) => {} _ => {} }\nlet x: f64 = r;\n}"),
+            ("Binding with the wrong type", "fn main() -> u64 {}\nfn f(s: Shape) -> u64 {\nmatch (s) { Circle(r) => { let x: i64 = r; } _ => {} }\n}"),
+            ("Break outside a loop", "fn main() -> u64 {}\nfn f(s: Shape) -> u64 {\nmatch (s) { _ => { break; } }\n}"),
+        ];
+        for (msg, input) in cases {
+            assert_some_error(msg, &format!("{}{}", shape, input));
+        }
+
+        let checked = check_program(
+            r#"enum Shape { Circle(f64), Rect(i64, i64), Empty }
+            enum Wrapper { Byte(u8), Pair(u8, Shape), Points([i32; 3]) }
+            struct Holder { tag: u8, shape: Shape }
+            fn main() -> u64 {
+            let h: Holder = Holder { tag: 'a', shape: Shape::Rect(1, 2) };
+            let p: *Shape = &h.shape;
+            *p = Shape::Circle(0.5);
+            let w: Wrapper = Wrapper::Pair(1u8, h.shape);
+            while (true) {
+                match (w) {
+                    Pair(b, s) => {
+                        match (s) {
+                            Rect(_, h) => { let area: i64 = h * 2; }
+                            _ => { break; }
+                        }
+                    }
+                    Byte(b) => { continue; }
+                    Points(points) => { let first: i32 = points[0]; }
+                }
+            }
+            }
+            "#,
+        )
+        .unwrap();
+        let env = &checked.program_env;
+        let layout = |name: &str| {
+            let def = env.get_enum(name).unwrap();
+            let offsets: Vec<Vec<usize>> = def.variants.iter().map(|v| v.payload.iter().map(|f| f.offset).collect()).collect();
+            (offsets, def.size, def.align)
+        };
+        assert_eq!(layout("Shape"), (vec![vec![8], vec![8, 16], vec![]], 24, 8));
+        assert_eq!(layout("Wrapper"), (vec![vec![8], vec![8, 16], vec![8]], 40, 8));
+        assert_eq!(env.size_of(&Type_::Named("Holder".to_string(), FI::zero())), 32);
+    }
+
+    #[test]
+    fn test_block_scopes() {
+        assert_some_error(
+            "Variable not found after its block closes",
+            r#"fn main() -> u64 {
+            if (true) {
+                let a: u64 = 5u64;
+            }
+            a = 6u64;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Loop variable not found after the loop",
+            r#"fn main() -> u64 {
+            for (i: i64 = 0; i < 10; i = i + 1) {}
+            return i;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Duplicate variable name in a nested scope",
+            r#"fn main() -> u64 {
+            while (true) {
+                let a: u64 = 5u64;
+                let a: u64 = 6u64;
+            }
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Shadowed variable has the inner type",
+            r#"fn main() -> u64 {
+            let a: u64 = 5u64;
+            if (true) {
+                let a: bool = true;
+                a = 6u64;
+            }
+            }
+            "#,
+        );
+
+        let prog = check_program(
+            r#"fn main() -> u64 {
+            let a: u64 = 5u64;
+            if (true) {
+                let a: i64 = 1;
+                let b: i64 = 2;
+            } else {
+                let b: bool = false;
+            }
+            for (i: i64 = 0; i < 10; i = i + 1) {}
+            for (i: u64 = 0u64; i < 10u64; i = i + 1u64) {}
+            }
+            "#,
+        )
+        .unwrap();
+        let names: Vec<&str> = prog.function_envs[0]
+            .local_variables
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["a", "a", "b", "b", "i", "i"]);
+        // the code generator finds each slot by where it is declared
+        let f_env = &prog.function_envs[0];
+        assert_eq!(f_env.local_slot("a", f_env.local_fis[0]), 0);
+        assert_eq!(f_env.local_slot("a", f_env.local_fis[1]), 1);
+        assert_eq!(f_env.local_slot("i", f_env.local_fis[5]), 5);
+    }
+
+    #[test]
+    fn test_use_before_declaration() {
+        assert_some_error(
+            "Variable used before its declaration",
+            r#"fn main() -> u64 {
+            let b: u64 = a;
+            let a: u64 = 5u64;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Variable assigned before its declaration",
+            r#"fn main() -> u64 {
+            a = 6u64;
+            let a: u64 = 5u64;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Variable used in a nested block before its declaration",
+            r#"fn main() -> u64 {
+            if (true) {
+                a = 6u64;
+            }
+            let a: u64 = 5u64;
+            }
+            "#,
+        );
+
+        assert_some_error(
+            "Variable used in its own initializer",
+            r#"fn main() -> u64 {
+            let a: u64 = a + 1u64;
+            }
+            "#,
+        );
+
+        let err = single_error(
+            r#"fn main() -> u64 {
+            let b: u64 = 1u64 + a;
+            let a: u64 = 5u64;
+            }
+            "#,
+        );
+        assert_eq!(err.msg, "Variable a used before its declaration");
+        assert_eq!(err.primary.fi, FI::new(1, 51));
+    }
+
+    #[test]
+    fn test_global_errors() {
+        let cases = [
+            ("Unsigned integers not allowed in global expressions", "global a: u64 = 1u64;"),
+            ("Comparison operators not allowed in global expressions", "global a: bool = 1 < 2;"),
+            ("Function calls not allowed in global expressions", "global a: i64 = f();"),
+            ("Division by zero in global expression", "global a: i64 = 1 / (2 - 2);"),
+            ("Global b not found, globals can only use other globals", "global a: i64 = b + 1;"),
+        ];
+        for (msg, input) in cases {
+            let err = single_error(&format!("{}\nfn main() -> u64 {{}}\nfn f() -> i64 {{}}", input));
+            assert_eq!(err.msg, msg);
+        }
+
+        let input = r#"global a: i64 = b;
+global b: i64 = c * 2;
+global c: i64 = a + 1;
+global d: i64 = 4;
+fn main() -> u64 {}"#;
+        let err = single_error(input);
+        assert_eq!(err.msg, "Cyclic dependency in global variables: a -> b -> c -> a");
+        assert_eq!(err.primary.fi.offset, 0);
+        let labels: Vec<(&str, usize)> = err.secondary.iter().map(|l| (l.msg.as_str(), l.fi.offset)).collect();
+        assert_eq!(labels, vec![("b depends on c", 19), ("c depends on a", 42)]);
+
+        let err = single_error("global a: i64 = -a;\nfn main() -> u64 {}");
+        assert_eq!(err.msg, "Cyclic dependency in global variables: a -> a");
+
+        // every failing global is reported, the ones using a failed global are skipped
+        let input = r#"global a: i64 = f();
+global b: bool = 1 < 2;
+global c: i64 = 1 / 0;
+global d: i64 = e;
+global s: i64 = "s";
+global g: i64 = c + 1;
+global h: i64 = 2;
+global k: u8 = h * 2;
+fn main() -> u64 {}
+fn f() -> i64 {}"#;
+        let errors = check_program(input).unwrap_err();
+        let msgs: Vec<&str> = errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(msgs.len(), 6, "{:?}", msgs);
+        assert!(msgs.contains(&"Function calls not allowed in global expressions"));
+        assert!(msgs.contains(&"Comparison operators not allowed in global expressions"));
+        assert!(msgs.contains(&"Division by zero in global expression"));
+        assert!(msgs.contains(&"Global e not found, globals can only use other globals"));
+        assert!(msgs.contains(&"Type mismatch in global: expected `u8`, found `i64`"));
+    }
+
+    #[test]
+    fn test_multiple_errors() {
+        let input = r#"fn main() -> u64 {
+            let a: u64 = 1;
+            let b: bool = a + 2u64;
+            for (i: u64 = 0u64; i < a; i = i + 1u64) {
+                c = true;
+            }
+            if (b) {
+                let d: u64 = a;
+                d = i;
+            }
+            return a;
+        }
+        fn f() -> i64 {
+            return true;
+        }
+        fn f() -> i64 {}
+        "#;
+        let errors = check_program(input).unwrap_err();
+        let msgs: Vec<&str> = errors.iter().map(|e| e.msg.split(':').next().unwrap()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Duplicate function name f",
+                "Type mismatch in let statement",
+                "Type mismatch in let statement",
+                "Variable c not found",
+                "Variable i not found",
+                "Type mismatch in return statement",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_labels() {
+        let input = "fn add(a: u8) -> u8 {}\nfn main() -> u64 {\n  let x: u16 = 7;\n  add(x);\n}";
+        let errors = check_program(input).unwrap_err();
+        assert_eq!(errors.len(), 2);
+
+        let let_error = &errors[0];
+        assert_eq!(let_error.msg, "Type mismatch in let statement: expected `u16`, found `i64`");
+        assert_eq!(let_error.primary.fi.offset, input.find('7').unwrap());
+        assert_eq!(let_error.primary.msg, "this is `i64`");
+        assert_eq!(let_error.secondary[0].fi.offset, input.find("u16").unwrap());
+        assert_eq!(let_error.help, vec!["write the literal as `7u16`"]);
+
+        let call_error = &errors[1];
+        assert_eq!(call_error.msg, "Type mismatch in function call: expected `u8`, found `u16`");
+        assert_eq!(call_error.secondary[0].msg, "function `add` declared here");
+        assert_eq!(call_error.secondary[0].fi.offset, 0);
+        assert!(call_error.help.is_empty());
+
+        let err = single_error("fn main() -> u64 {\n  let q: u8 = 300;\n}");
+        assert_eq!(err.help, vec!["`300` does not fit in `u8`, write `300 as u8` to wrap it around"]);
+
+        let err = single_error("fn main() -> u64 {}\nfn main() -> u64 {}");
+        assert_eq!(err.primary.fi.offset, 20);
+        assert_eq!(err.secondary[0].msg, "first declared here");
+        assert_eq!(err.secondary[0].fi.offset, 0);
+    }
+
+    #[test]
+    fn test_unreachable_warning() {
+        let input = r#"fn main() -> u64 {
+            while (true) {
+                break;
+                let a: u64 = 1u64;
+            }
+            return 0u64;
+        }
+        "#;
+        let prog = check_program(input).unwrap();
+        assert_eq!(prog.warnings.len(), 1);
+        let warning = &prog.warnings[0];
+        assert!(!warning.is_error());
+        assert_eq!(warning.msg, "Unreachable statement");
+        assert_eq!(warning.primary.fi.offset, input.find("let").unwrap());
+        assert_eq!(warning.secondary[0].fi.offset, input.find("break").unwrap());
+    }
+
+    #[test]
+    fn test_warnings_with_errors() {
+        let input = r#"fn main() -> u64 {
+            let z: u64 = w;
+            return 0u64;
+            w = 3u64;
+        }
+        "#;
+        let errors = check_program(input).unwrap_err();
+        let warnings: Vec<&CheckError> = errors.iter().filter(|e| !e.is_error()).collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].msg, "Unreachable statement");
+        assert_eq!(errors.iter().filter(|e| e.is_error()).count(), 2);
+    }
+
+    fn single_error(input: &str) -> CheckError {
+        let mut errors = check_program(input).unwrap_err();
+        assert_eq!(errors.len(), 1, "expected a single error, got {:#?}", errors);
+        errors.remove(0)
+    }
+
+    fn assert_some_error(msg: &str, input: &str) {
+        if let Ok(got) = check_program(input) {
+            panic!(
+                "Expected error: {}\n\ngot program:\n{:#?}",
+                msg,
+                got
+            );
         }
     }
 }
@@ -1,26 +1,51 @@
 use crate::ast::*;
 use crate::checked_program::*;
-use crate::parser::parse_program;
+use crate::diagnostic::Diagnostic;
+use crate::file_info::SourceMap;
+use crate::parser::{parse_program, ParseError};
 use crate::tokenizer;
 use std::fs::File;
 use std::io::prelude::*;
 
+#[derive(Debug, Default)]
+pub struct CompileOptions {
+    // check array indexes at runtime, exits with a message when out of range
+    pub bounds_check: bool,
+}
+
 pub struct CodeGenerator {
     assembly: String,
     lable_counter: u32,
+    // (continue label, break label) of the enclosing loops, innermost last
+    loop_labels: Vec<(String, String)>,
+    bounds_check: bool,
+    // array lengths that need an out of bounds handler
+    bounds_lengths: Vec<u64>,
+    // contents of the string literals, the index is the number in their label
+    strings: Vec<Vec<u8>>,
+    // (label, arm label per tag) of the jump tables of large matches
+    jump_tables: Vec<(String, Vec<String>)>,
 }
 
+// enums with at least this many variants are matched through a jump table, smaller ones through compares
+const JUMP_TABLE_MIN_VARIANTS: usize = 4;
+
 impl CodeGenerator {
-    pub fn generate_code(prog: CheckedProgram) -> String {
+    pub fn generate_code(prog: CheckedProgram, options: &CompileOptions) -> String {
         let mut code_generator = CodeGenerator {
             assembly: String::new(),
             lable_counter: 0,
+            loop_labels: Vec::new(),
+            bounds_check: options.bounds_check,
+            bounds_lengths: Vec::new(),
+            strings: Vec::new(),
+            jump_tables: Vec::new(),
         };
         code_generator.generate_program(prog);
         code_generator.assembly
     }
 
-    fn generate_program(&mut self, prog: CheckedProgram) {
+    fn generate_program(&mut self, mut prog: CheckedProgram) {
         self.assembly.push_str(
             "
 global _start
@@ -38,15 +63,123 @@ _start:
 
         // enumerate functions
         for (i, function) in prog.prog.functions.iter().enumerate() {
-            self.generate_function(function, &prog.program_env, &prog.function_envs[i]);
+            self.generate_function(function, &prog.program_env, &mut prog.function_envs[i]);
         }
 
+        self.generate_bounds_handlers();
+
         self.assembly.push_str("\n\nsection .data\n");
         for (i, global) in prog.program_env.globals_def.iter().enumerate() {
-            let value = &prog.program_env.global_values[i];
+            let values = &prog.program_env.global_values[i];
+            if matches!(global.ttype.scalar(), Type_::Named(_, _)) {
+                self.generate_struct_global(global, values, &prog.program_env);
+                continue;
+            }
+            let size = global.ttype.scalar().size();
+            let values: Vec<String> = values.iter().map(|x| Self::data_value(size, *x)).collect();
             self.assembly
-                .push_str(&format!("{} dq {}\n", global.name, value));
+                .push_str(&format!("{} {} {}\n", global.name, Self::data_directive(size), values.join(", ")));
+        }
+        for len in &self.bounds_lengths {
+            self.assembly.push_str(&format!(
+                "bounds_msg_{} db \"index out of bounds: the length is {}\", 10\n",
+                len, len
+            ));
+        }
+
+        self.assembly.push_str("\n\nsection .rodata\n");
+        for (i, bytes) in self.strings.iter().enumerate() {
+            // the bytes are nul terminated for system calls that take paths, the length does not count it
+            let mut values: Vec<String> = bytes.iter().map(|x| x.to_string()).collect();
+            values.push("0".to_string());
+            self.assembly
+                .push_str(&format!("str_{}_data db {}\n", i, values.join(", ")));
+            // the value of a string is this pointer and length pair
+            self.assembly
+                .push_str(&format!("str_{} dq str_{}_data, {}\n", i, i, bytes.len()));
+        }
+        for (label, targets) in &self.jump_tables {
+            self.assembly.push_str(&format!("{} dq {}\n", label, targets.join(", ")));
+        }
+    }
+
+    /// Structs mix scalar sizes and have padding between fields, so every scalar gets its own line.
+    fn generate_struct_global(&mut self, global: &Variable, values: &[i64], p_env: &ProgEnv) {
+        let mut scalars = Vec::new();
+        Self::scalar_layout(&global.ttype, 0, p_env, &mut scalars);
+        self.add_label(&global.name);
+        let mut offset = 0;
+        for ((scalar_offset, scalar), value) in scalars.iter().zip(values) {
+            self.generate_data_padding(scalar_offset - offset);
+            let size = scalar.size();
+            self.add_asm(&format!("{} {}", Self::data_directive(size), Self::data_value(size, *value)));
+            offset = scalar_offset + size;
+        }
+        self.generate_data_padding(p_env.size_of(&global.ttype) - offset);
+    }
+
+    /// The offset and type of every scalar in a value, in the order global values are flattened.
+    fn scalar_layout(ttype: &Type_, offset: usize, p_env: &ProgEnv, out: &mut Vec<(usize, Type_)>) {
+        match ttype {
+            Type_::Named(name, _) => {
+                for field in &p_env.get_struct(name).expect("checked struct").fields {
+                    Self::scalar_layout(&field.ttype, offset + field.offset, p_env, out);
+                }
+            }
+            Type_::Array(elem, len, _) => {
+                let elem_size = p_env.size_of(elem);
+                for i in 0..*len as usize {
+                    Self::scalar_layout(elem, offset + i * elem_size, p_env, out);
+                }
+            }
+            _ => out.push((offset, ttype.clone())),
+        }
+    }
+
+    fn generate_data_padding(&mut self, bytes: usize) {
+        if bytes > 0 {
+            self.add_asm(&format!("db {}", vec!["0"; bytes].join(", ")));
+        }
+    }
+
+    fn data_directive(size: usize) -> &'static str {
+        match size {
+            1 => "db",
+            2 => "dw",
+            4 => "dd",
+            _ => "dq",
+        }
+    }
+
+    /// Values are evaluated as i64, narrow types keep only their low bytes.
+    fn data_value(size: usize, value: i64) -> String {
+        match size {
+            1 => (value as u8).to_string(),
+            2 => (value as u16).to_string(),
+            4 => (value as u32).to_string(),
+            _ => value.to_string(),
+        }
+    }
+
+    /// One entry per array length, they load the message and share the code that prints it and exits.
+    fn generate_bounds_handlers(&mut self) {
+        if self.bounds_lengths.is_empty() {
+            return;
         }
+        for len in self.bounds_lengths.clone() {
+            let msg_len = format!("index out of bounds: the length is {}", len).len() + 1;
+            self.add_label(&format!("bounds_error_{}", len));
+            self.add_asm(&format!("lea rsi, [bounds_msg_{}]", len));
+            self.add_asm(&format!("mov rdx, {}", msg_len));
+            self.add_asm("jmp bounds_check_failed");
+        }
+        self.add_label("bounds_check_failed");
+        self.add_asm("mov rax, 1 ; write");
+        self.add_asm("mov rdi, 2 ; stderr");
+        self.add_asm("syscall");
+        self.add_asm("mov rax, 60 ; exit");
+        self.add_asm("mov rdi, 101");
+        self.add_asm("syscall");
     }
 
     fn add_asm(&mut self, s: &str) {
@@ -56,37 +189,46 @@ _start:
         self.assembly.push_str(&format!("{}:\n", s));
     }
 
-    fn generate_function(&mut self, function: &Function, prog_env: &ProgEnv, func_env: &FuncEnv) {
+    fn generate_function(&mut self, function: &Function, prog_env: &ProgEnv, func_env: &mut FuncEnv) {
         self.add_label(&function.name);
         self.add_asm("; prologue");
         self.add_asm("push rbp");
         self.add_asm("mov rbp, rsp");
         // update stack pointer for local variables
-        let bytes_local_variables = func_env.local_variables.len() * 8;
+        let bytes_local_variables = func_env.locals_size();
         self.add_asm(&format!("sub rsp, {}", bytes_local_variables));
 
         self.add_asm("; body");
-        for stmt in &function.body {
-            self.generate_statement(stmt, prog_env, func_env);
-        }
+        func_env.rewind();
+        self.generate_block(&function.body, prog_env, func_env);
 
         self.add_asm("; epilogue");
         self.generate_function_epilogue(func_env);
     }
 
     fn generate_function_epilogue(&mut self, func_env: &FuncEnv) {
-        let bytes_local_variables = func_env.local_variables.len() * 8;
+        let bytes_local_variables = func_env.locals_size();
         self.add_asm(&format!("add rsp, {}", bytes_local_variables));
         self.add_asm("pop rbp");
         self.add_asm("ret");
     }
 
-    fn generate_statement(&mut self, stmt: &Stmt, p_env: &ProgEnv, f_env: &FuncEnv) {
+    /// Mirrors `CheckedProgram::check_block` so locals resolve to the same stack slots.
+    fn generate_block(&mut self, stmts: &[Stmt], p_env: &ProgEnv, f_env: &mut FuncEnv) {
+        f_env.enter_block(stmts);
+        for stmt in stmts {
+            self.generate_statement(stmt, p_env, f_env);
+        }
+        f_env.exit_scope();
+    }
+
+    fn generate_statement(&mut self, stmt: &Stmt, p_env: &ProgEnv, f_env: &mut FuncEnv) {
         match stmt {
             Stmt::Let(let_stmt) => self.generate_let_statement(let_stmt, p_env, f_env),
             Stmt::If(if_stmt) => self.generate_if_statement(if_stmt, p_env, f_env),
             Stmt::While(while_stmt) => self.generate_while_stmt(while_stmt, p_env, f_env),
             Stmt::DoWhile(do_while) => self.generate_do_while_stmt(do_while, p_env, f_env),
+            Stmt::For(for_stmt) => self.generate_for_stmt(for_stmt, p_env, f_env),
             Stmt::Assign(assign_stmt) => self.generate_assign_statement(assign_stmt, p_env, f_env),
             Stmt::Return(return_stmt) => {
                 self.generate_expression(&return_stmt.value, p_env, f_env);
@@ -95,6 +237,15 @@ _start:
             }
             Stmt::Asm(asm) => self.generate_asm_block(asm, p_env, f_env),
             Stmt::Call(call) => self.generate_call(call, p_env, f_env),
+            Stmt::Match(match_stmt) => self.generate_match_stmt(match_stmt, p_env, f_env),
+            Stmt::Break(_) => {
+                let (_, break_label) = self.loop_labels.last().expect("break outside of a loop");
+                self.add_asm(&format!("jmp {}", break_label));
+            }
+            Stmt::Continue(_) => {
+                let (continue_label, _) = self.loop_labels.last().expect("continue outside of a loop");
+                self.add_asm(&format!("jmp {}", continue_label));
+            }
         }
     }
 
@@ -121,29 +272,198 @@ _start:
     fn get_var_address(var_name: &str, f_env: &FuncEnv, p_env: &ProgEnv) -> String {
         let var_num = f_env.get_local_pos(var_name);
         if let Some(var_num) = var_num {
-            let offset = (var_num as i64 + 1) * 8;
-            return format!("[rbp - {}]", offset);
+            return Self::local_address(var_num, f_env);
         }
         let param_num = f_env.get_param_pos(var_name);
         if let Some(param_num) = param_num {
-            let num_rev = f_env.function_params.len() - param_num - 1;
             let const_offset = 24; // rbp, return address, return value
-            let offset = (num_rev as i64) * 8 + const_offset;
+            let offset = f_env.param_offset(param_num) + const_offset;
             return format!("[rbp + {}]", offset);
         }
-        if let Some(_) = p_env.get_global_def(var_name) {
+        if p_env.get_global_def(var_name).is_some() {
             return format!("[{}]", var_name);
         }
         panic!("Variable not found: {}, {:?}", var_name, p_env.globals_def);
     }
 
+    fn local_address(pos: usize, f_env: &FuncEnv) -> String {
+        format!("[rbp - {}]", f_env.local_offset(pos))
+    }
+
     fn generate_assign_statement(&mut self, assign: &Assign, p_env: &ProgEnv, f_env: &FuncEnv) {
-        self.generate_expression(&assign.value, p_env, f_env);
-        let var_address = Self::get_var_address(&assign.name, f_env, p_env);
-        self.add_asm(&format!("mov {}, rax", var_address));
+        match &assign.target {
+            Exp::Var(name, _) if !CheckedProgram::get_type(&assign.target, f_env, p_env).is_aggregate() => {
+                self.generate_expression(&assign.value, p_env, f_env);
+                let var_address = Self::get_var_address(name, f_env, p_env);
+                let ttype = CheckedProgram::get_type(&assign.target, f_env, p_env);
+                self.generate_scalar_store(&ttype, &var_address);
+            }
+            // the elements may read the target, like `a = [a[1], a[0]]`, so the literal is built in a
+            // temporary on the stack and copied over once all of them are evaluated
+            _ if matches!(assign.value, Exp::Array(_, _) | Exp::Struct(_, _, _) | Exp::Variant(_, _, _, _)) => {
+                let size = p_env.size_of(&CheckedProgram::get_type(&assign.value, f_env, p_env));
+                let temp_size = size.div_ceil(8) * 8;
+                self.generate_address(&assign.target, p_env, f_env);
+                self.add_asm("push rax");
+                self.add_asm(&format!("sub rsp, {}", temp_size));
+                self.add_asm("push rsp");
+                self.generate_store(&assign.value, p_env, f_env);
+                self.add_asm("mov rsi, rsp");
+                self.add_asm(&format!("mov rdi, [rsp + {}]", temp_size));
+                self.add_asm(&format!("mov rcx, {}", size));
+                self.add_asm("rep movsb");
+                self.add_asm(&format!("add rsp, {}", temp_size + 8));
+            }
+            _ => {
+                self.generate_address(&assign.target, p_env, f_env);
+                self.add_asm("push rax");
+                self.generate_store(&assign.value, p_env, f_env);
+            }
+        }
+    }
+
+    /// Stores `value` at the address on top of the stack, and pops that address.
+    fn generate_store(&mut self, value: &Exp, p_env: &ProgEnv, f_env: &FuncEnv) {
+        match value {
+            Exp::Array(elements, _) => {
+                let elem_size = p_env.size_of(&CheckedProgram::get_type(&elements[0], f_env, p_env));
+                for (i, element) in elements.iter().enumerate() {
+                    self.add_asm("mov rax, [rsp]");
+                    self.add_asm(&format!("add rax, {}", i * elem_size));
+                    self.add_asm("push rax");
+                    self.generate_store(element, p_env, f_env);
+                }
+                self.add_asm("add rsp, 8");
+            }
+            Exp::Struct(name, fields, _) => {
+                let def = p_env.get_struct(name).expect("checked struct");
+                for (field_name, value) in fields {
+                    let offset = def.get_field(field_name).expect("checked field").offset;
+                    self.add_asm("mov rax, [rsp]");
+                    self.add_asm(&format!("add rax, {}", offset));
+                    self.add_asm("push rax");
+                    self.generate_store(value, p_env, f_env);
+                }
+                self.add_asm("add rsp, 8");
+            }
+            Exp::Variant(name, variant_name, payload, _) => {
+                let def = p_env.get_enum(name).expect("checked enum");
+                let variant = def.get_variant(variant_name).expect("checked variant");
+                self.add_asm("mov rax, [rsp]");
+                self.add_asm(&format!("mov qword [rax], {}", variant.tag));
+                for (value, field) in payload.iter().zip(&variant.payload) {
+                    self.add_asm("mov rax, [rsp]");
+                    self.add_asm(&format!("add rax, {}", field.offset));
+                    self.add_asm("push rax");
+                    self.generate_store(value, p_env, f_env);
+                }
+                self.add_asm("add rsp, 8");
+            }
+            _ => {
+                let ttype = CheckedProgram::get_type(value, f_env, p_env);
+                self.generate_expression(value, p_env, f_env);
+                self.add_asm("pop rbx");
+                if ttype.is_aggregate() {
+                    // rax holds the address of the source, copy it byte by byte
+                    self.add_asm("mov rsi, rax");
+                    self.add_asm("mov rdi, rbx");
+                    self.add_asm(&format!("mov rcx, {}", p_env.size_of(&ttype)));
+                    self.add_asm("rep movsb");
+                } else {
+                    self.generate_scalar_store(&ttype, "[rbx]");
+                }
+            }
+        }
+    }
+
+    /// Loads the scalar at `address` into rax, narrow types are sign or zero extended to 64 bits.
+    fn generate_load(&mut self, ttype: &Type_, address: &str) {
+        let signed = ttype.is_signed();
+        match ttype.size() {
+            1 if signed => self.add_asm(&format!("movsx rax, byte {}", address)),
+            1 => self.add_asm(&format!("movzx rax, byte {}", address)),
+            2 if signed => self.add_asm(&format!("movsx rax, word {}", address)),
+            2 => self.add_asm(&format!("movzx rax, word {}", address)),
+            4 if signed => self.add_asm(&format!("movsxd rax, dword {}", address)),
+            4 => self.add_asm(&format!("mov eax, dword {}", address)), // writing eax clears the upper half
+            _ => self.add_asm(&format!("mov rax, {}", address)),
+        }
     }
 
-    fn generate_do_while_stmt(&mut self, do_while: &DoWhile, p_env: &ProgEnv, f_env: &FuncEnv) {
+    /// Stores rax to `address`, writing only as many bytes as the type has.
+    fn generate_scalar_store(&mut self, ttype: &Type_, address: &str) {
+        match ttype.size() {
+            1 => self.add_asm(&format!("mov byte {}, al", address)),
+            2 => self.add_asm(&format!("mov word {}, ax", address)),
+            4 => self.add_asm(&format!("mov dword {}, eax", address)),
+            _ => self.add_asm(&format!("mov {}, rax", address)),
+        }
+    }
+
+    /// Wraps rax around to the width of `ttype` and extends it back to 64 bits, narrow values are
+    /// always kept extended in registers so 64 bit comparisons and divisions work on them.
+    fn generate_extend(&mut self, ttype: &Type_) {
+        let signed = ttype.is_signed();
+        match ttype.size() {
+            1 if signed => self.add_asm("movsx rax, al"),
+            1 => self.add_asm("movzx rax, al"),
+            2 if signed => self.add_asm("movsx rax, ax"),
+            2 => self.add_asm("movzx rax, ax"),
+            4 if signed => self.add_asm("movsxd rax, eax"),
+            4 => self.add_asm("mov eax, eax"),
+            _ => {}
+        }
+    }
+
+    /// Puts the address of a place expression (see `Expression::is_place`) in rax.
+    fn generate_address(&mut self, exp: &Exp, p_env: &ProgEnv, f_env: &FuncEnv) {
+        match exp {
+            Exp::Var(name, _) => {
+                let var_address = Self::get_var_address(name, f_env, p_env);
+                self.add_asm(&format!("lea rax, {}", var_address));
+            }
+            // the address of `*p` is the value of p
+            Exp::UnaryOp(UnOp::Deref, e, _) => self.generate_expression(e, p_env, f_env),
+            Exp::Index(array, index, _) => {
+                let Type_::Array(elem, len, _) = CheckedProgram::get_type(array, f_env, p_env) else {
+                    panic!("Indexing a non array: {:?}", array);
+                };
+                self.generate_address(array, p_env, f_env);
+                self.add_asm("push rax");
+                self.generate_expression(index, p_env, f_env);
+                if self.bounds_check {
+                    // negative indexes are huge unsigned numbers, so one comparison covers both ends
+                    self.add_asm(&format!("cmp rax, {}", len));
+                    self.add_asm(&format!("jae bounds_error_{}", len));
+                    if !self.bounds_lengths.contains(&len) {
+                        self.bounds_lengths.push(len);
+                    }
+                }
+                self.add_asm(&format!("imul rax, rax, {}", p_env.size_of(&elem)));
+                self.add_asm("pop rbx");
+                self.add_asm("add rax, rbx");
+            }
+            Exp::Field(base, name, _) => {
+                // aggregates evaluate to their address, this also covers string literals
+                self.generate_expression(base, p_env, f_env);
+                let offset = match (CheckedProgram::get_type(base, f_env, p_env), name.as_str()) {
+                    (Type_::Str(_), "ptr") => 0,
+                    (Type_::Str(_), "len") => 8,
+                    (Type_::Named(struct_name, _), _) => {
+                        let def = p_env.get_struct(&struct_name).expect("checked struct");
+                        def.get_field(name).expect("checked field").offset
+                    }
+                    (ttype, _) => panic!("Type {:?} has no field {}", ttype, name),
+                };
+                if offset != 0 {
+                    self.add_asm(&format!("add rax, {}", offset));
+                }
+            }
+            _ => panic!("Not a place expression: {:?}", exp),
+        }
+    }
+
+    fn generate_do_while_stmt(&mut self, do_while: &DoWhile, p_env: &ProgEnv, f_env: &mut FuncEnv) {
         let label_count = self.lable_counter;
         self.lable_counter += 1;
 
@@ -152,35 +472,16 @@ _start:
         let end_label = format!("do_while_end_{}", label_count);
 
         self.add_label(&body_label);
-        for stmt in &do_while.body {
-            self.generate_statement(stmt, p_env, f_env);
-        }
+        self.loop_labels.push((condition_label.clone(), end_label.clone()));
+        self.generate_block(&do_while.body, p_env, f_env);
+        self.loop_labels.pop();
 
         self.add_label(&condition_label);
-        match &do_while.condition {
-            Exp::BinOp(ref e1, op, ref e2, _) => {
-                self.generate_expression(e1, p_env, f_env);
-                self.add_asm("push rax");
-                self.generate_expression(e2, p_env, f_env);
-                self.add_asm("pop rbx");
-                self.add_asm("cmp rbx, rax");
-                let jmp = match op {
-                    Op::LT => "jl",
-                    Op::GT => "jg",
-                    Op::Ne => "jne",
-                    Op::Eq => "je",
-                    _ => panic!("unimplemented"),
-                };
-                self.add_asm(&format!("{} {}", jmp, body_label));
-            }
-            _ => {
-                panic!("unimplemented");
-            }
-        }
+        self.generate_condition_jump(&do_while.condition, &body_label, true, p_env, f_env);
         self.add_label(&end_label);
     }
 
-    fn generate_while_stmt(&mut self, while_stmt: &While, p_env: &ProgEnv, f_env: &FuncEnv) {
+    fn generate_while_stmt(&mut self, while_stmt: &While, p_env: &ProgEnv, f_env: &mut FuncEnv) {
         let label_count = self.lable_counter;
         self.lable_counter += 1;
 
@@ -189,82 +490,221 @@ _start:
         let end_label = format!("while_end_{}", label_count);
 
         self.add_label(&condition_label);
-        match &while_stmt.condition {
-            Exp::BinOp(ref e1, op, ref e2, _) => {
-                self.generate_expression(e1, p_env, f_env);
-                self.add_asm("push rax");
-                self.generate_expression(e2, p_env, f_env);
-                self.add_asm("pop rbx");
-                self.add_asm("cmp rbx, rax");
-                let jmp = match op {
-                    Op::LT => "jge",
-                    Op::GT => "jle",
-                    Op::Ne => "je",
-                    Op::Eq => "jne",
-                    _ => panic!("unimplemented"),
-                };
-                self.add_asm(&format!("{} {}", jmp, end_label));
-            }
-            _ => panic!("unimplemented"),
-        }
+        self.generate_condition_jump(&while_stmt.condition, &end_label, false, p_env, f_env);
 
         self.add_label(&body_label);
-        for stmt in &while_stmt.body {
-            self.generate_statement(stmt, p_env, f_env);
-        }
+        self.loop_labels.push((condition_label.clone(), end_label.clone()));
+        self.generate_block(&while_stmt.body, p_env, f_env);
+        self.loop_labels.pop();
+        self.add_asm(&format!("jmp {}", condition_label));
+        self.add_label(&end_label);
+    }
+
+    fn generate_for_stmt(&mut self, for_stmt: &For, p_env: &ProgEnv, f_env: &mut FuncEnv) {
+        let label_count = self.lable_counter;
+        self.lable_counter += 1;
+
+        let condition_label = format!("for_condition_{}", label_count);
+        let body_label = format!("for_body_{}", label_count);
+        let step_label = format!("for_step_{}", label_count);
+        let end_label = format!("for_end_{}", label_count);
+
+        f_env.enter_scope();
+        self.generate_let_statement(&for_stmt.init, p_env, f_env);
+
+        self.add_label(&condition_label);
+        self.generate_condition_jump(&for_stmt.condition, &end_label, false, p_env, f_env);
+
+        self.add_label(&body_label);
+        // continue runs the step before checking the condition again
+        self.loop_labels.push((step_label.clone(), end_label.clone()));
+        self.generate_block(&for_stmt.body, p_env, f_env);
+        self.loop_labels.pop();
+
+        self.add_label(&step_label);
+        self.generate_assign_statement(&for_stmt.step, p_env, f_env);
         self.add_asm(&format!("jmp {}", condition_label));
         self.add_label(&end_label);
+        f_env.exit_scope();
     }
 
-    fn generate_if_statement(&mut self, if_stmt: &If, p_env: &ProgEnv, f_env: &FuncEnv) {
+    fn generate_if_statement(&mut self, if_stmt: &If, p_env: &ProgEnv, f_env: &mut FuncEnv) {
         let label_count = self.lable_counter;
         self.lable_counter += 1;
 
+        let end_label = format!("end_{}", label_count);
+        self.generate_if_branch(if_stmt, label_count, &end_label, p_env, f_env);
+        self.add_label(&end_label);
+    }
+
+    /// Generates one arm of an if/else if/else chain, every arm jumps to the same `end_label`.
+    fn generate_if_branch(&mut self, if_stmt: &If, label_count: u32, end_label: &str, p_env: &ProgEnv, f_env: &mut FuncEnv) {
         let if_condition_label = format!("if_condition_{}", label_count);
         let if_body_label = format!("if_body_{}", label_count);
         let else_label = format!("else_{}", label_count);
-        let end_label = format!("end_{}", label_count);
 
         self.add_label(&if_condition_label);
-        match &if_stmt.condition {
-            Exp::BinOp(ref e1, op, ref e2, _) => {
+        self.generate_condition_jump(&if_stmt.condition, &else_label, false, p_env, f_env);
+        self.add_label(&if_body_label);
+        self.generate_block(&if_stmt.body, p_env, f_env);
+
+        self.add_asm(&format!("jmp {}", end_label));
+        self.add_label(&else_label);
+
+        match if_stmt.else_body.as_slice() {
+            [Stmt::If(else_if)] => {
+                let label_count = self.lable_counter;
+                self.lable_counter += 1;
+                self.generate_if_branch(else_if, label_count, end_label, p_env, f_env);
+            }
+            else_body => self.generate_block(else_body, p_env, f_env),
+        }
+    }
+
+    /// Emits a jump to `label` that is taken when `condition` evaluates to `jump_if`.
+    /// `&&` and `||` short-circuit, the right side is only evaluated when needed.
+    fn generate_condition_jump(&mut self, condition: &Exp, label: &str, jump_if: bool, p_env: &ProgEnv, f_env: &FuncEnv) {
+        match condition {
+            Exp::BinOp(ref e1, op @ (Op::And | Op::Or), ref e2, _) => {
+                // `a && b` is false as soon as `a` is false, `a || b` is true as soon as `a` is true
+                let short_circuit_on = *op == Op::Or;
+                if jump_if == short_circuit_on {
+                    self.generate_condition_jump(e1, label, jump_if, p_env, f_env);
+                    self.generate_condition_jump(e2, label, jump_if, p_env, f_env);
+                } else {
+                    let label_count = self.lable_counter;
+                    self.lable_counter += 1;
+                    let skip_label = format!("logic_skip_{}", label_count);
+
+                    self.generate_condition_jump(e1, &skip_label, short_circuit_on, p_env, f_env);
+                    self.generate_condition_jump(e2, label, jump_if, p_env, f_env);
+                    self.add_label(&skip_label);
+                }
+            }
+            Exp::UnaryOp(UnOp::Not, ref e, _) => {
+                self.generate_condition_jump(e, label, !jump_if, p_env, f_env);
+            }
+            // float comparisons need more than one flag for NaN, they go through the value below
+            Exp::BinOp(ref e1, op, ref e2, _)
+                if op.is_comparison() && !CheckedProgram::get_type(e1, f_env, p_env).is_float() =>
+            {
                 self.generate_expression(e1, p_env, f_env);
                 self.add_asm("push rax");
                 self.generate_expression(e2, p_env, f_env);
                 self.add_asm("pop rbx");
                 self.add_asm("cmp rbx, rax");
-                let jmp = match op {
-                    Op::LT => "jge",
-                    Op::GT => "jle",
-                    Op::Ne => "je",
-                    Op::Eq => "jne",
-                    _ => panic!("unimplemented"),
+                let signed = CheckedProgram::get_type(e1, f_env, p_env).is_signed();
+                let cc = if jump_if {
+                    Self::condition_code(op, signed)
+                } else {
+                    Self::condition_code(&op.negated_comparison(), signed)
                 };
-                self.add_asm(&format!("{} {}", jmp, else_label));
+                self.add_asm(&format!("j{} {}", cc, label));
+            }
+            _ => {
+                // any other bool expression is 0 or 1 in rax
+                self.generate_expression(condition, p_env, f_env);
+                self.add_asm("test rax, rax");
+                let jmp = if jump_if { "jnz" } else { "jz" };
+                self.add_asm(&format!("{} {}", jmp, label));
             }
-            _ => panic!("unimplemented"),
         }
-        self.add_label(&if_body_label);
-        for stmt in &if_stmt.body {
-            self.generate_statement(stmt, p_env, f_env);
+    }
+
+    fn generate_let_statement(&mut self, let_stmt: &Let, p_env: &ProgEnv, f_env: &mut FuncEnv) {
+        // bound after the value, so the initializer still sees any outer variable of the same name
+        let pos = f_env.local_slot(&let_stmt.name, let_stmt.fi);
+        let var_address = Self::local_address(pos, f_env);
+        if let_stmt.ttype.is_aggregate() {
+            self.add_asm(&format!("lea rax, {}", var_address));
+            self.add_asm("push rax");
+            self.generate_store(&let_stmt.value, p_env, f_env);
+        } else {
+            self.generate_expression(&let_stmt.value, p_env, f_env);
+            self.generate_scalar_store(&let_stmt.ttype, &var_address);
         }
+        f_env.bind_local(pos);
+    }
 
-        self.add_asm(&format!("jmp {}", end_label));
-        self.add_label(&else_label);
+    /// Jumps to the arm of the tag, the enum's address stays on the stack until the arm has copied its payload.
+    fn generate_match_stmt(&mut self, match_stmt: &Match, p_env: &ProgEnv, f_env: &mut FuncEnv) {
+        let label_count = self.lable_counter;
+        self.lable_counter += 1;
 
-        for stmt in &if_stmt.else_body {
-            self.generate_statement(stmt, p_env, f_env);
+        let end_label = format!("match_end_{}", label_count);
+        let arm_labels: Vec<String> = (0..match_stmt.arms.len())
+            .map(|i| format!("match_{}_arm_{}", label_count, i))
+            .collect();
+
+        let ttype = CheckedProgram::get_type(&match_stmt.value, f_env, p_env);
+        let Type_::Named(name, _) = ttype else { panic!("match on {:?}", ttype) };
+        let def = p_env.get_enum(&name).expect("checked enum");
+        // the arm of every tag, variants without an arm of their own go to `_`
+        let targets: Vec<usize> = def
+            .variants
+            .iter()
+            .map(|variant| {
+                match_stmt
+                    .arms
+                    .iter()
+                    .position(|arm| arm.variant.as_ref().is_none_or(|x| *x == variant.name))
+                    .expect("checked match is exhaustive")
+            })
+            .collect();
+
+        self.generate_expression(&match_stmt.value, p_env, f_env);
+        self.add_asm("push rax");
+        self.add_asm("mov rax, [rax]");
+        if targets.len() >= JUMP_TABLE_MIN_VARIANTS {
+            let table_label = format!("match_table_{}", label_count);
+            self.add_asm(&format!("lea rbx, [{}]", table_label));
+            self.add_asm("jmp qword [rbx + rax * 8]");
+            let table = targets.iter().map(|&arm| arm_labels[arm].clone()).collect();
+            self.jump_tables.push((table_label, table));
+        } else {
+            // the last tag needs no compare, it is the only one left
+            let (last, rest) = targets.split_last().expect("enums have variants");
+            for (tag, arm) in rest.iter().enumerate() {
+                self.add_asm(&format!("cmp rax, {}", tag));
+                self.add_asm(&format!("je {}", arm_labels[*arm]));
+            }
+            self.add_asm(&format!("jmp {}", arm_labels[*last]));
         }
 
+        for (arm, arm_label) in match_stmt.arms.iter().zip(&arm_labels) {
+            self.add_label(arm_label);
+            // mirrors `CheckedProgram::check_match`, the bindings are bound before the statements
+            f_env.enter_block(&arm.body);
+            let variant = arm.variant.as_ref().map(|x| def.get_variant(x).expect("checked variant"));
+            let payload = variant.map(|x| x.payload.as_slice()).unwrap_or_default();
+            for (binding, field) in arm.bindings.iter().zip(payload) {
+                if binding == "_" {
+                    continue;
+                }
+                let pos = f_env.local_slot(binding, arm.fi);
+                let var_address = Self::local_address(pos, f_env);
+                self.add_asm("mov rbx, [rsp]");
+                if field.ttype.is_aggregate() {
+                    self.add_asm(&format!("lea rsi, [rbx + {}]", field.offset));
+                    self.add_asm(&format!("lea rdi, {}", var_address));
+                    self.add_asm(&format!("mov rcx, {}", p_env.size_of(&field.ttype)));
+                    self.add_asm("rep movsb");
+                } else {
+                    self.generate_load(&field.ttype, &format!("[rbx + {}]", field.offset));
+                    self.generate_scalar_store(&field.ttype, &var_address);
+                }
+                f_env.bind_local(pos);
+            }
+            self.add_asm("add rsp, 8");
+            for stmt in &arm.body {
+                self.generate_statement(stmt, p_env, f_env);
+            }
+            f_env.exit_scope();
+            self.add_asm(&format!("jmp {}", end_label));
+        }
         self.add_label(&end_label);
     }
 
-    fn generate_let_statement(&mut self, let_stmt: &Let, p_env: &ProgEnv, f_env: &FuncEnv) {
-        self.generate_expression(&let_stmt.value, p_env, f_env);
-        let var_address = Self::get_var_address(&let_stmt.name, f_env, p_env);
-        self.add_asm(&format!("mov {}, rax", var_address));
-    }
-
     fn generate_expression(&mut self, exp: &Exp, p_env: &ProgEnv, f_env: &FuncEnv) {
         match exp {
             Exp::U64(number, _) => {
@@ -273,9 +713,80 @@ _start:
             Exp::I64(number, _) => {
                 self.add_asm(&format!("mov rax, {}", number));
             }
+            Exp::U32(number, _) => {
+                self.add_asm(&format!("mov rax, {}", number));
+            }
+            Exp::I32(number, _) => {
+                self.add_asm(&format!("mov rax, {}", number));
+            }
+            Exp::U16(number, _) => {
+                self.add_asm(&format!("mov rax, {}", number));
+            }
+            Exp::I16(number, _) => {
+                self.add_asm(&format!("mov rax, {}", number));
+            }
+            Exp::U8(number, _) => {
+                self.add_asm(&format!("mov rax, {}", number));
+            }
+            Exp::I8(number, _) => {
+                self.add_asm(&format!("mov rax, {}", number));
+            }
+            // floats live in general registers as their bits, they only move to xmm registers to be operated on
+            Exp::F64(number, _) => {
+                self.add_asm(&format!("mov rax, 0x{:x} ; {:?}", number.to_bits(), number));
+            }
+            Exp::Bool(value, _) => {
+                self.add_asm(&format!("mov rax, {}", *value as u64));
+            }
             Exp::Var(name, _) => {
                 let var_address = Self::get_var_address(name, f_env, p_env);
-                self.add_asm(&format!("mov rax, {}", var_address));
+                let ttype = CheckedProgram::get_type(exp, f_env, p_env);
+                // aggregates evaluate to their address
+                if ttype.is_aggregate() {
+                    self.add_asm(&format!("lea rax, {}", var_address));
+                } else {
+                    self.generate_load(&ttype, &var_address);
+                }
+            }
+            Exp::Index(_, _, _) => {
+                self.generate_address(exp, p_env, f_env);
+                let ttype = CheckedProgram::get_type(exp, f_env, p_env);
+                if !ttype.is_aggregate() {
+                    self.generate_load(&ttype, "[rax]");
+                }
+            }
+            Exp::Array(_, _) | Exp::Struct(_, _, _) | Exp::Variant(_, _, _, _) => {
+                panic!("Array, struct and enum literals are only generated as stored values: {:?}", exp)
+            }
+            Exp::Str(bytes, _) => {
+                let n = match self.strings.iter().position(|x| x == bytes) {
+                    Some(n) => n,
+                    None => {
+                        self.strings.push(bytes.clone());
+                        self.strings.len() - 1
+                    }
+                };
+                self.add_asm(&format!("lea rax, [str_{}]", n));
+            }
+            Exp::Field(_, _, _) => {
+                self.generate_address(exp, p_env, f_env);
+                let ttype = CheckedProgram::get_type(exp, f_env, p_env);
+                if !ttype.is_aggregate() {
+                    self.generate_load(&ttype, "[rax]");
+                }
+            }
+            Exp::BinOp(e1, op @ (Op::And | Op::Or), e2, _) => {
+                let label_count = self.lable_counter;
+                self.lable_counter += 1;
+                let end_label = format!("logic_end_{}", label_count);
+
+                // rax already holds the result if the left side decides it
+                self.generate_expression(e1, p_env, f_env);
+                self.add_asm("test rax, rax");
+                let jmp = if *op == Op::And { "jz" } else { "jnz" };
+                self.add_asm(&format!("{} {}", jmp, end_label));
+                self.generate_expression(e2, p_env, f_env);
+                self.add_label(&end_label);
             }
             Exp::BinOp(e1, op, e2, _) => {
                 self.generate_expression(e1, p_env, f_env);
@@ -283,10 +794,54 @@ _start:
                 self.generate_expression(e2, p_env, f_env);
                 self.add_asm("mov rbx, rax");
                 self.add_asm("pop rax");
-                let t = CheckedProgram::get_type(exp, f_env, p_env); 
+                // the operand type decides between signed and unsigned instructions
+                let t = CheckedProgram::get_type(e1, f_env, p_env);
                 match t {
-                    Type_::U64(_) => self.generate_binop_u64(op), 
+                    Type_::U64(_) | Type_::Bool(_) => self.generate_binop_u64(op),
                     Type_::I64(_) => self.generate_binop_i64(op),
+                    Type_::F64(_) => self.generate_binop_f64(op),
+                    Type_::U32(_) | Type_::I32(_) | Type_::U16(_) | Type_::I16(_) | Type_::U8(_) | Type_::I8(_) => {
+                        self.generate_binop_narrow(op, &t)
+                    }
+                    Type_::Ptr(inner, _) => {
+                        let rhs_pointer = CheckedProgram::get_type(e2, f_env, p_env).is_pointer();
+                        self.generate_binop_ptr(op, p_env.size_of(&inner), rhs_pointer);
+                    }
+                    Type_::Array(_, _, _) | Type_::Str(_) | Type_::Named(_, _) => {
+                        panic!("Operators do not apply to aggregates: {:?}", exp)
+                    }
+                }
+            }
+            Exp::UnaryOp(UnOp::AddrOf, e, _) => self.generate_address(e, p_env, f_env),
+            Exp::Cast(e, ttype, _) => {
+                self.generate_expression(e, p_env, f_env);
+                let from = CheckedProgram::get_type(e, f_env, p_env);
+                self.generate_cast(&from, ttype);
+            }
+            Exp::UnaryOp(op, e, _) => {
+                self.generate_expression(e, p_env, f_env);
+                match op {
+                    UnOp::Not => self.add_asm("xor rax, 1"),
+                    UnOp::BitNot => {
+                        self.add_asm("not rax");
+                        self.generate_extend(&CheckedProgram::get_type(exp, f_env, p_env));
+                    }
+                    UnOp::Neg => {
+                        let ttype = CheckedProgram::get_type(exp, f_env, p_env);
+                        if ttype.is_float() {
+                            self.add_asm("btc rax, 63"); // flip the sign bit
+                        } else {
+                            self.add_asm("neg rax");
+                            self.generate_extend(&ttype);
+                        }
+                    }
+                    UnOp::Deref => {
+                        let ttype = CheckedProgram::get_type(exp, f_env, p_env);
+                        if !ttype.is_aggregate() {
+                            self.generate_load(&ttype, "[rax]");
+                        }
+                    }
+                    UnOp::AddrOf => unreachable!("address of is generated without evaluating the operand"),
                 }
             }
             Exp::Call(call) => {
@@ -295,6 +850,24 @@ _start:
         }
     }
 
+    /// Pointer arithmetic, integer offsets and differences are counted in elements.
+    fn generate_binop_ptr(&mut self, op: &Operator, elem_size: usize, rhs_pointer: bool) {
+        match op {
+            Operator::Sub if rhs_pointer => {
+                self.add_asm("sub rax, rbx");
+                self.add_asm("cqo"); // sign extend rax into rdx
+                self.add_asm(&format!("mov rbx, {}", elem_size));
+                self.add_asm("idiv rbx");
+            }
+            Operator::Add | Operator::Sub => {
+                self.add_asm(&format!("imul rbx, rbx, {}", elem_size));
+                self.generate_binop_u64(op);
+            }
+            // comparisons, pointers are unsigned
+            _ => self.generate_binop_u64(op),
+        }
+    }
+
     fn generate_binop_u64(&mut self, op: &Operator) {
         match op {
             Operator::Add => self.add_asm("add rax, rbx"),
@@ -309,8 +882,151 @@ _start:
                 self.add_asm("div rbx"); // rdx := rdx:rax % rbx
                 self.add_asm("mov rax, rdx");
             }
+            Operator::BitAnd => self.add_asm("and rax, rbx"),
+            Operator::BitOr => self.add_asm("or rax, rbx"),
+            Operator::BitXor => self.add_asm("xor rax, rbx"),
+            Operator::Shl => {
+                self.add_asm("mov rcx, rbx"); // shift count must be in cl
+                self.add_asm("shl rax, cl");
+            }
+            Operator::Shr => {
+                self.add_asm("mov rcx, rbx");
+                self.add_asm("shr rax, cl"); // logical shift, fills with zeros
+            }
+            Operator::Eq | Operator::Ne | Operator::LT | Operator::GT | Operator::LE | Operator::GE => {
+                self.generate_comparison(op, false)
+            }
+            _ => panic!("unimplemented, {:?}", op),
+        }
+    }
+
+    /// Operands are extended to 64 bits, so the 64 bit operations work as long as the result is
+    /// wrapped back to the width of the type. Division uses the faster 32 bit `div`.
+    fn generate_binop_narrow(&mut self, op: &Operator, ttype: &Type_) {
+        let signed = ttype.is_signed();
+        match op {
+            Operator::Div | Operator::Mod => {
+                if signed {
+                    self.add_asm("cdq"); // sign extend eax into edx
+                    self.add_asm("idiv ebx"); // eax := edx:eax / ebx, edx := edx:eax % ebx
+                } else {
+                    self.add_asm("xor edx, edx");
+                    self.add_asm("div ebx");
+                }
+                if *op == Operator::Mod {
+                    self.add_asm("mov eax, edx");
+                }
+            }
+            _ if signed => self.generate_binop_i64(op),
+            _ => self.generate_binop_u64(op),
+        }
+        if !op.is_comparison() {
+            self.generate_extend(ttype);
+        }
+    }
+
+    fn generate_binop_f64(&mut self, op: &Operator) {
+        self.add_asm("movq xmm0, rax");
+        self.add_asm("movq xmm1, rbx");
+        match op {
+            Operator::Add => self.add_asm("addsd xmm0, xmm1"),
+            Operator::Sub => self.add_asm("subsd xmm0, xmm1"),
+            Operator::Mul => self.add_asm("mulsd xmm0, xmm1"),
+            Operator::Div => self.add_asm("divsd xmm0, xmm1"),
+            // ucomisd sets the flags like an unsigned compare, and all of ZF, PF and CF when
+            // either side is NaN. Only `above` conditions are false for NaN, so `<` swaps the operands.
+            Operator::GT | Operator::GE => {
+                self.add_asm("ucomisd xmm0, xmm1");
+                self.add_asm(&format!("set{} al", Self::condition_code(op, false)));
+            }
+            Operator::LT | Operator::LE => {
+                self.add_asm("ucomisd xmm1, xmm0");
+                let swapped = if *op == Operator::LT { Operator::GT } else { Operator::GE };
+                self.add_asm(&format!("set{} al", Self::condition_code(&swapped, false)));
+            }
+            Operator::Eq => {
+                self.add_asm("ucomisd xmm0, xmm1");
+                self.add_asm("sete al");
+                self.add_asm("setnp cl"); // unordered, NaN is not equal to anything
+                self.add_asm("and al, cl");
+            }
+            Operator::Ne => {
+                self.add_asm("ucomisd xmm0, xmm1");
+                self.add_asm("setne al");
+                self.add_asm("setp cl");
+                self.add_asm("or al, cl");
+            }
             _ => panic!("unimplemented, {:?}", op),
         }
+        if op.is_comparison() {
+            self.add_asm("movzx rax, al");
+        } else {
+            self.add_asm("movq rax, xmm0");
+        }
+    }
+
+    /// Converts rax from `from` to `to`. Pointers and integers share registers so only the width
+    /// of integers changes, conversions to and from f64 go through xmm0.
+    fn generate_cast(&mut self, from: &Type_, to: &Type_) {
+        match (from.is_float(), to.is_float()) {
+            (false, false) => self.generate_extend(to),
+            (true, true) => {}
+            (false, true) => {
+                if matches!(from, Type_::U64(_)) {
+                    self.generate_u64_to_f64();
+                } else {
+                    // narrow integers are already extended to 64 bits
+                    self.add_asm("cvtsi2sd xmm0, rax");
+                }
+                self.add_asm("movq rax, xmm0");
+            }
+            (true, false) => {
+                self.add_asm("movq xmm0, rax");
+                if matches!(to, Type_::U64(_)) {
+                    self.generate_f64_to_u64();
+                } else {
+                    self.add_asm("cvttsd2si rax, xmm0"); // truncates toward zero
+                    self.generate_extend(to);
+                }
+            }
+        }
+    }
+
+    /// `cvtsi2sd` only converts signed values, numbers with the top bit set are halved (keeping
+    /// the lowest bit so it still rounds correctly), converted and doubled.
+    fn generate_u64_to_f64(&mut self) {
+        let label_count = self.lable_counter;
+        self.lable_counter += 1;
+        self.add_asm("test rax, rax");
+        self.add_asm(&format!("js u64_to_f64_big_{}", label_count));
+        self.add_asm("cvtsi2sd xmm0, rax");
+        self.add_asm(&format!("jmp u64_to_f64_end_{}", label_count));
+        self.add_label(&format!("u64_to_f64_big_{}", label_count));
+        self.add_asm("mov rbx, rax");
+        self.add_asm("shr rbx, 1");
+        self.add_asm("and rax, 1");
+        self.add_asm("or rbx, rax");
+        self.add_asm("cvtsi2sd xmm0, rbx");
+        self.add_asm("addsd xmm0, xmm0");
+        self.add_label(&format!("u64_to_f64_end_{}", label_count));
+    }
+
+    /// `cvttsd2si` only produces signed values, numbers from 2^63 up are converted with 2^63
+    /// subtracted and the top bit set again afterwards.
+    fn generate_f64_to_u64(&mut self) {
+        let label_count = self.lable_counter;
+        self.lable_counter += 1;
+        self.add_asm(&format!("mov rbx, 0x{:x} ; 2^63", 2f64.powi(63).to_bits()));
+        self.add_asm("movq xmm1, rbx");
+        self.add_asm("ucomisd xmm0, xmm1");
+        self.add_asm(&format!("jae f64_to_u64_big_{}", label_count));
+        self.add_asm("cvttsd2si rax, xmm0");
+        self.add_asm(&format!("jmp f64_to_u64_end_{}", label_count));
+        self.add_label(&format!("f64_to_u64_big_{}", label_count));
+        self.add_asm("subsd xmm0, xmm1");
+        self.add_asm("cvttsd2si rax, xmm0");
+        self.add_asm("btc rax, 63");
+        self.add_label(&format!("f64_to_u64_end_{}", label_count));
     }
 
     fn generate_binop_i64(&mut self, op: &Operator) {
@@ -327,28 +1043,79 @@ _start:
                 self.add_asm("idiv rbx"); // rdx := rdx:rax % rbx
                 self.add_asm("mov rax, rdx");
             }
+            Operator::BitAnd => self.add_asm("and rax, rbx"),
+            Operator::BitOr => self.add_asm("or rax, rbx"),
+            Operator::BitXor => self.add_asm("xor rax, rbx"),
+            Operator::Shl => {
+                self.add_asm("mov rcx, rbx"); // shift count must be in cl
+                self.add_asm("shl rax, cl");
+            }
+            Operator::Shr => {
+                self.add_asm("mov rcx, rbx");
+                self.add_asm("sar rax, cl"); // arithmetic shift, keeps the sign
+            }
+            Operator::Eq | Operator::Ne | Operator::LT | Operator::GT | Operator::LE | Operator::GE => {
+                self.generate_comparison(op, true)
+            }
             _ => panic!("unimplemented, {:?}", op),
         }
     }
 
+    /// Compares rax with rbx and leaves 1 in rax if `op` holds, 0 otherwise.
+    fn generate_comparison(&mut self, op: &Operator, signed: bool) {
+        self.add_asm("cmp rax, rbx");
+        self.add_asm(&format!("set{} al", Self::condition_code(op, signed)));
+        self.add_asm("movzx rax, al");
+    }
+
+    /// Condition code suffix for `jcc`/`setcc` after a `cmp` of the left and right operands.
+    /// Unsigned values use below/above, signed values less/greater.
+    fn condition_code(op: &Operator, signed: bool) -> &'static str {
+        match (op, signed) {
+            (Op::Eq, _) => "e",
+            (Op::Ne, _) => "ne",
+            (Op::LT, true) => "l",
+            (Op::LT, false) => "b",
+            (Op::GT, true) => "g",
+            (Op::GT, false) => "a",
+            (Op::LE, true) => "le",
+            (Op::LE, false) => "be",
+            (Op::GE, true) => "ge",
+            (Op::GE, false) => "ae",
+            _ => panic!("not a comparison, {:?}", op),
+        }
+    }
+
     fn generate_call(&mut self, call: &Call, p_env: &ProgEnv, f_env: &FuncEnv) {
+        let mut args_size = 0;
         for arg in &call.args {
-            self.generate_expression(arg, p_env, f_env);
-            self.add_asm("push rax");
+            let ttype = CheckedProgram::get_type(arg, f_env, p_env);
+            if ttype.is_aggregate() {
+                // make room for the value on the stack and store it there, this also covers literals
+                let size = p_env.size_of(&ttype).next_multiple_of(8);
+                self.add_asm(&format!("sub rsp, {}", size));
+                self.add_asm("push rsp");
+                self.generate_store(arg, p_env, f_env);
+                args_size += size;
+            } else {
+                self.generate_expression(arg, p_env, f_env);
+                self.add_asm("push rax");
+                args_size += 8;
+            }
         }
         // save space for return value
         self.add_asm("sub rsp, 8");
         self.add_asm(&format!("call {}", call.name));
         // move return value to rax
         self.add_asm("mov rax, [rsp]");
-        let stack_offset = call.args.len() * 8 + 8;
+        let stack_offset = args_size + 8;
         // remove arguments from stack
         self.add_asm(&format!("add rsp, {}", stack_offset));
     }
 }
 
-pub fn compile(checked_prog: CheckedProgram, out_file: &str) -> Result<(), String> {
-    let assembly = CodeGenerator::generate_code(checked_prog);
+pub fn compile(checked_prog: CheckedProgram, out_file: &str, options: &CompileOptions) -> Result<(), String> {
+    let assembly = CodeGenerator::generate_code(checked_prog, options);
 
     let assembly_file = format!("{}.asm", out_file);
     let object_file = format!("{}.o", out_file);
@@ -356,7 +1123,7 @@ pub fn compile(checked_prog: CheckedProgram, out_file: &str) -> Result<(), Strin
 
     save_to_file(&assembly_file, &assembly);
     nasm(&assembly_file, &object_file);
-    ld(&object_file, &executable_file);
+    ld(&object_file, executable_file);
 
     delete_file(&object_file);
     Ok(())
@@ -400,25 +1167,38 @@ pub fn nasm(infile: &str, outfile: &str) {
     }
 }
 
-pub fn compile_file(filename: &str) -> Result<(), String> {
-    let input = std::fs::read_to_string(filename).map_err(|e| e.to_string())?;
-    let tokens = tokenizer::tokenize(&input);
-    let prog = parse_program(tokens);
-    if let Err(e) = prog {
-        e.pretty_print(&input);
-        return Err(format!("parse error: {:?}", e));
+/// Prints the diagnostics in the order they appear in the source, returns a summary with the
+/// number of errors, warnings are printed but not counted.
+fn report(diagnostics: &mut [Box<Diagnostic>], sources: &SourceMap, kind: &str) -> String {
+    diagnostics.sort_by_key(|d| (d.primary.fi.file, d.primary.fi.offset));
+    for d in diagnostics.iter() {
+        d.pretty_print(sources);
+    }
+    let count = diagnostics.iter().filter(|d| d.is_error()).count();
+    if count == 1 {
+        format!("1 {} error", kind)
+    } else {
+        format!("{} {} errors", count, kind)
     }
-    let prog = prog.unwrap();
+}
+
+pub fn compile_file(filename: &str, options: &CompileOptions) -> Result<(), String> {
+    let input = std::fs::read_to_string(filename).map_err(|e| e.to_string())?;
+    let mut sources = SourceMap::default();
+    let file = sources.add(filename, input);
+    let tokens = tokenizer::tokenize(&sources.get(file).input, file)
+        .map_err(|e| report(&mut [e.diagnostic()], &sources, "lex"))?;
+
+    let prog = parse_program(tokens)
+        .map_err(|errors| report(&mut errors.iter().map(ParseError::diagnostic).collect::<Vec<_>>(), &sources, "parse"))?;
 
-    let checked_prog = CheckedProgram::check(prog);
-    if let Err(e) = checked_prog {
-        e.pretty_print(&input);
-        return Err(format!("type error: {:?}", e));
+    let checked_prog = CheckedProgram::check(prog).map_err(|mut errors| report(&mut errors, &sources, "type"))?;
+    for warning in &checked_prog.warnings {
+        warning.pretty_print(&sources);
     }
-    let checked_prog = checked_prog.unwrap();
 
     let out_filename = filename.replace(".mylang", "");
-    compile(checked_prog, &out_filename)
+    compile(checked_prog, &out_filename, options)
 }
 
 #[cfg(test)]
@@ -433,7 +1213,7 @@ mod tests {
                 continue;
             }
 
-            compile_file(&file).expect("compile error");
+            compile_file(&file, &CompileOptions::default()).expect("compile error");
 
             let prog_name = file.replace(".mylang", "");
             let output = std::process::Command::new(&prog_name)
@@ -450,6 +1230,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bounds_check() {
+        let program = r#"
+        fn main() -> u64 {
+            let a: [i64; 3] = [1, 2, 3];
+            let i: i64 = INDEX;
+            a[i] = 4;
+            exit(0);
+        }
+        fn exit(code: i64) -> u64 {
+            asm {
+                mov rax, 60
+                mov rdi, {code}
+                syscall
+            }
+        }
+        "#;
+        let options = CompileOptions { bounds_check: true };
+        let dir = std::env::temp_dir();
+        for (n, (index, exit_code)) in [("2", 0), ("3", 101), ("-1", 101)].into_iter().enumerate() {
+            let file = dir.join(format!("mylang_bounds_check_{}.mylang", n));
+            let file = file.to_str().expect("to_str failed");
+            save_to_file(file, &program.replace("INDEX", index));
+            compile_file(file, &options).expect("compile error");
+
+            let prog_name = file.replace(".mylang", "");
+            let output = std::process::Command::new(&prog_name)
+                .output()
+                .expect("failed to execute process");
+            assert_eq!(output.status.code(), Some(exit_code), "index: {}", index);
+            if exit_code != 0 {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                assert_eq!(stderr, "index out of bounds: the length is 3\n");
+            }
+
+            delete_file(file);
+            delete_file(&prog_name);
+            delete_file(&format!("{}.asm", prog_name));
+        }
+    }
+
     fn get_all_files(dir: &str) -> Vec<String> {
         let paths = std::fs::read_dir(dir).expect("read_dir failed");
         let mut files = Vec::new();
@@ -0,0 +1,144 @@
+use crate::file_info::{underline, SourceMap, FI};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A span of source with a short text printed next to its underline.
+#[derive(Debug, PartialEq)]
+pub struct Label {
+    pub fi: FI,
+    pub msg: String,
+}
+
+/// A problem found in the source. The primary label is where it is, the secondary ones are
+/// other places that explain it, like the declaration a value has to match.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub msg: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+/// Diagnostics are built boxed, they are returned as the error of most checks and would
+/// otherwise make every `Result` as large as all their labels and notes.
+impl Diagnostic {
+    /// An error at `fi`, the primary label has no text until `with_label`.
+    pub fn new(msg: &str, fi: FI) -> Box<Diagnostic> {
+        Box::new(Diagnostic {
+            severity: Severity::Error,
+            msg: msg.to_string(),
+            primary: Label { fi, msg: String::new() },
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+        })
+    }
+
+    pub fn warning(msg: &str, fi: FI) -> Box<Diagnostic> {
+        let mut diagnostic = Diagnostic::new(msg, fi);
+        diagnostic.severity = Severity::Warning;
+        diagnostic
+    }
+
+    pub fn with_label(mut self: Box<Self>, msg: &str) -> Box<Diagnostic> {
+        self.primary.msg = msg.to_string();
+        self
+    }
+
+    pub fn with_secondary(mut self: Box<Self>, fi: FI, msg: &str) -> Box<Diagnostic> {
+        self.secondary.push(Label { fi, msg: msg.to_string() });
+        self
+    }
+
+    pub fn with_note(mut self: Box<Self>, msg: &str) -> Box<Diagnostic> {
+        self.notes.push(msg.to_string());
+        self
+    }
+
+    pub fn with_help(mut self: Box<Self>, msg: &str) -> Box<Diagnostic> {
+        self.help.push(msg.to_string());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// `file:line:col: error: msg`, the primary span underlined with `^` and the secondary ones
+    /// with `-`, each with its location, then the notes and help.
+    pub fn render(&self, sources: &SourceMap, color: bool) -> String {
+        let (kind, primary_color) = match self.severity {
+            Severity::Error => ("error", "\x1b[31m"),    // red
+            Severity::Warning => ("warning", "\x1b[33m"), // yellow
+        };
+        let color_of = |code: &'static str| if color { code } else { "" };
+
+        let mut out_str = format!("{}: {}: {}\n", sources.location(&self.primary.fi), kind, self.msg);
+        out_str.push_str(&Self::render_label(&self.primary, '^', color_of(primary_color), sources));
+        for label in &self.secondary {
+            out_str.push_str(&format!("  --> {}\n", sources.location(&label.fi)));
+            out_str.push_str(&Self::render_label(label, '-', color_of("\x1b[34m"), sources)); // blue
+        }
+        for note in &self.notes {
+            out_str.push_str(&format!("  = note: {}\n", note));
+        }
+        for help in &self.help {
+            out_str.push_str(&format!("  = help: {}\n", help));
+        }
+        out_str
+    }
+
+    fn render_label(label: &Label, marker: char, color: &str, sources: &SourceMap) -> String {
+        let mut out_str = underline(sources.get(label.fi.file), &label.fi, marker, color);
+        if !label.msg.is_empty() {
+            out_str.push(' ');
+            out_str.push_str(&label.msg);
+        }
+        out_str.push('\n');
+        out_str
+    }
+
+    pub fn pretty_print(&self, sources: &SourceMap) {
+        println!("{}", self.render(sources, true));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render() {
+        let mut sources = SourceMap::default();
+        let file = sources.add("add.mylang", "fn add(a: u64) -> u64 {}\nfn main() -> u64 {\n  add(true);\n}".to_string());
+        let input = &sources.get(file).input;
+        let arg = FI { length: 4, offset: input.find("true").unwrap(), file };
+        let decl = FI { length: 24, offset: 0, file };
+        let diagnostic = Diagnostic::new("Type mismatch in function call: expected u64, found bool", arg)
+            .with_label("this is bool")
+            .with_secondary(decl, "function `add` declared here")
+            .with_note("parameters are never converted")
+            .with_help("compare it, like `x != 0`");
+
+        assert_eq!(diagnostic.render(&sources, false), r#"
+add.mylang:3:7: error: Type mismatch in function call: expected u64, found bool
+  3:   add(true);
+           ^^^^ this is bool
+  --> add.mylang:1:1
+  1: fn add(a: u64) -> u64 {}
+     ------------------------ function `add` declared here
+  = note: parameters are never converted
+  = help: compare it, like `x != 0`
+"#[1..]);
+
+        let warning = Diagnostic::warning("Unreachable statement", arg);
+        assert!(warning.render(&sources, false).starts_with("add.mylang:3:7: warning: Unreachable statement\n"));
+    }
+}
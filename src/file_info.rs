@@ -1,17 +1,26 @@
 pub type FI = FileInfo;
 
+/// Index of a file in the `SourceMap`.
+pub type FileId = usize;
+
+/// A span of source, `offset` and `length` are in bytes.
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct FileInfo {
     pub length: usize,
     pub offset: usize,
+    pub file: FileId,
 }
 
 impl FileInfo {
+    /// A span in the first file, the only one for now.
     pub fn new(length: usize, offset: usize) -> FileInfo {
-        FileInfo { length, offset }
+        FileInfo { length, offset, file: 0 }
     }
     pub fn zero() -> FileInfo {
-        FileInfo { length: 0, offset: 0 }
+        FileInfo::new(0, 0)
+    }
+    pub fn start_of(file: FileId) -> FileInfo {
+        FileInfo { length: 0, offset: 0, file }
     }
 
     pub fn inc(&mut self) {
@@ -19,83 +28,105 @@ impl FileInfo {
         self.offset += 1;
     }
 
+    /// Moves past `c`, which can take more than one byte.
+    pub fn advance(&mut self, c: char) {
+        self.length += c.len_utf8();
+        self.offset += c.len_utf8();
+    }
+
     pub fn len_diff(&self, start: &FileInfo) -> FileInfo {
-        FileInfo::new(self.length - start.length, start.offset)
+        FileInfo { length: self.length - start.length, ..*start }
     }
 
     pub fn merge(&self, end: &FileInfo) -> FileInfo {
-        FileInfo::new(end.offset + end.length - self.offset, self.offset)
+        FileInfo { length: end.offset + end.length - self.offset, ..*self }
     }
 }
 
+/// The files being compiled, they are read once and their lines are indexed for the error messages.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn add(&mut self, name: &str, input: String) -> FileId {
+        self.files.push(SourceFile::new(name, input));
+        self.files.len() - 1
+    }
+
+    pub fn get(&self, file: FileId) -> &SourceFile {
+        &self.files[file]
+    }
+
+    /// `file:line:col`, the form editors jump to.
+    pub fn location(&self, fi: &FileInfo) -> String {
+        let file = self.get(fi.file);
+        let (line, col) = file.line_col(fi.offset);
+        format!("{}:{}:{}", file.name, line, col)
+    }
+}
 
-pub fn underline_error(input: &str, fi: &FileInfo) -> String {
-    underline(input, fi, "\x1b[31m") // underlined in red
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    pub input: String,
+    // byte offset where each line starts, the first one is 0
+    line_starts: Vec<usize>,
 }
 
-pub fn underline(input: &str, fi: &FileInfo, color: &str) -> String {
-        let mut offset = 0;
-        let mut col = 1;
-        let mut l_num = 1;
-
-        let mut prev_non_ws_line_offset = 0;
-        let mut prev_non_ws_line_num = 1;
-
-        
-        let mut ti = input.chars().peekable();
-        while offset < fi.offset {
-            let c = ti.next().unwrap();
-            match c {
-                '\n' => {
-                    l_num += 1;
-                    col = 1;
-                }
-                ' ' | '\t' => col += 1,
-                _ => {
-                    prev_non_ws_line_offset = offset - (col - 1);
-                    prev_non_ws_line_num = l_num;
-                    col += 1;
-                }
-            }
-            offset += 1;
-        }
-
-        let mut ti = input.chars().skip(prev_non_ws_line_offset).peekable();
-        let mut line_number = prev_non_ws_line_num;
-        let mut out_str = format!("{:3}: ", line_number);
-        let mut offset = prev_non_ws_line_offset;
-        // create 
-        while offset < fi.offset {
-            let c = ti.next().unwrap();
-            match c {
-                '\n' => {
-                    line_number += 1;
-                    out_str.push_str(&format!("\n{:3}: ", line_number));
-                }
-                _ => out_str.push(c),
-            }
-            offset += 1;
-        }
-        // add til the end of the line
-        while let Some(c) = ti.next() {
-            match c {
-                '\n' => {
-                    out_str.push_str("\n");
-                    break;
-                }
-                _ => out_str.push(c),
-            }
-        }
-        if !out_str.ends_with('\n') {
-            out_str.push_str("\n");
-        }
-        out_str.push_str(color); // color escape sequence
-        out_str.push_str(&format!("     {:->1$}", "^", col));
-        if color.len() > 0 {
-            out_str.push_str("\x1b[0m"); // reset
-        }
-        out_str
+impl SourceFile {
+    pub fn new(name: &str, input: String) -> SourceFile {
+        let line_starts = std::iter::once(0)
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceFile { name: name.to_string(), input, line_starts }
+    }
+
+    /// 0 based line holding the byte at `offset`.
+    fn line_index(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// 1 based line and column, the column counts characters so it matches what editors show.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_index(offset);
+        let start = self.line_starts[line];
+        (line + 1, self.input[start..offset].chars().count() + 1)
+    }
+
+    /// The text of a 0 based line, without its newline.
+    fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line];
+        let end = self.line_starts.get(line + 1).map_or(self.input.len(), |next| next - 1);
+        &self.input[start..end]
+    }
+}
 
+/// The lines from the last code before the span down to the span's line, with the span marked
+/// by `marker` on the line below. A span over several lines is marked up to the end of its first one.
+pub fn underline(file: &SourceFile, fi: &FileInfo, marker: char, color: &str) -> String {
+    let line = file.line_index(fi.offset);
+    // an error at the start of a line is often about what came before, like a missing `;`
+    let first_line = file.input[..fi.offset]
+        .rfind(|c: char| !matches!(c, ' ' | '\t' | '\n'))
+        .map_or(line, |prev| file.line_index(prev));
+
+    let mut out_str = String::new();
+    for l in first_line..=line {
+        out_str.push_str(&format!("{:3}: {}\n", l + 1, file.line(l)));
+    }
+    let (_, col) = file.line_col(fi.offset);
+    let line_end = file.line_starts.get(line + 1).map_or(file.input.len(), |next| next - 1);
+    let end = (fi.offset + fi.length).clamp(fi.offset, line_end);
+    let width = file.input[fi.offset..end].chars().count().max(1);
+    out_str.push_str(&" ".repeat(4 + col));
+    out_str.push_str(color); // color escape sequence
+    out_str.push_str(&marker.to_string().repeat(width));
+    if !color.is_empty() {
+        out_str.push_str("\x1b[0m"); // reset
+    }
+    out_str
 }
 
 #[cfg(test)]
@@ -105,31 +136,67 @@ mod test {
 
     #[test]
     fn test_underline() {
-        let input = r#"0123456789"#;
-        assert_eq!(underline(input, &FI::new(1, 0), ""), r#"
+        let file = SourceFile::new("test", "0123456789".to_string());
+        assert_eq!(underline(&file, &FI::new(1, 0), '^', ""), r#"
   1: 0123456789
      ^"#[1..]);
-        assert_eq!(underline(input, &FI::new(1, 1), ""), r#"
+        assert_eq!(underline(&file, &FI::new(1, 1), '^', ""), r#"
   1: 0123456789
-     -^"#[1..]);
-        assert_eq!(underline(input, &FI::new(1, 8), ""), r#"
+      ^"#[1..]);
+        assert_eq!(underline(&file, &FI::new(1, 8), '^', ""), r#"
   1: 0123456789
-     --------^"#[1..]);
+             ^"#[1..]);
     }
 
     #[test]
-    fn test_underline_with_previous_non_white_on_other_line() {
-        let input = r#"01234
+    fn test_underline_range() {
+        let file = SourceFile::new("test", "let x: u64 = 1;\nfn f() {\n}".to_string());
+        assert_eq!(underline(&file, &FI::new(3, 7), '^', ""), r#"
+  1: let x: u64 = 1;
+            ^^^"#[1..]);
+        // a span over several lines is marked to the end of its first one
+        assert_eq!(underline(&file, &FI::new(10, 16), '-', ""), r#"
+  1: let x: u64 = 1;
+  2: fn f() {
+     --------"#[1..]);
+    }
 
-789"#;
-        assert_eq!(underline(input, &FI::new(1, 7), ""), r#"
+    #[test]
+    fn test_line_col() {
+        let mut sources = SourceMap::default();
+        sources.add("a.mylang", "x".to_string());
+        let file = sources.add("b.mylang", "fn main() {\n  // é\n  x = 1;\n}".to_string());
+        let input = &sources.get(file).input;
+        let x = input.find('x').unwrap();
+        assert_eq!(sources.get(file).line_col(0), (1, 1));
+        assert_eq!(sources.get(file).line_col(x), (3, 3));
+        assert_eq!(sources.get(file).line_col(input.len()), (4, 2));
+        // the column counts characters, `é` is two bytes
+        let after = input.find('é').unwrap() + 'é'.len_utf8();
+        assert_eq!(sources.get(file).line_col(after), (2, 7));
+        assert_eq!(sources.location(&FI { length: 1, offset: x, file }), "b.mylang:3:3");
+    }
+
+    #[test]
+    fn test_underline_non_ascii() {
+        let file = SourceFile::new("test", "// ü\nlet ä = 1;".to_string());
+        let offset = file.input.find('=').unwrap();
+        assert_eq!(underline(&file, &FI::new(1, offset), '^', ""), r#"
+  2: let ä = 1;
+           ^"#[1..]);
+    }
+
+    #[test]
+    fn test_underline_with_previous_non_white_on_other_line() {
+        let file = SourceFile::new("test", "01234\n\n789".to_string());
+        assert_eq!(underline(&file, &FI::new(1, 7), '^', ""), r#"
   1: 01234
   2: 
   3: 789
      ^"#[1..]);
-        assert_eq!(underline(input, &FI::new(1, 8), ""), r#"
+        assert_eq!(underline(&file, &FI::new(1, 8), '^', ""), r#"
   3: 789
-     -^"#[1..]);
+      ^"#[1..]);
     }
     
 }
@@ -1,6 +1,10 @@
+// Token and AST names mirror the assembly/grammar terms (EOF, ASM)
+#![allow(clippy::upper_case_acronyms)]
+
 mod ast;
 mod checked_program;
 mod code_generation;
+mod diagnostic;
 mod parser;
 mod tokenizer;
 mod file_info;
@@ -8,12 +12,19 @@ mod file_info;
 use crate::code_generation::*;
 
 fn main() {
-    let mut file_name = "test.mylang";
+    let mut file_name = "test.mylang".to_string();
+    let mut options = CompileOptions::default();
     // check command line arguments
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 {
-        file_name = &args[1];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--bounds-check" => options.bounds_check = true,
+            _ => file_name = arg,
+        }
     }
 
-    compile_file(file_name).expect("compile error");
+    // the error is already printed with its location, exit without a backtrace
+    if let Err(e) = compile_file(&file_name, &options) {
+        eprintln!("compile error: {}", e);
+        std::process::exit(1);
+    }
 }
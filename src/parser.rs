@@ -1,6 +1,7 @@
 use crate::ast::*;
 use crate::tokenizer::{Token, KW, TT};
-use crate::file_info::{FI, underline_error};
+use crate::diagnostic::Diagnostic;
+use crate::file_info::FI;
 use std::iter::Peekable;
 use std::slice::Iter;
 
@@ -27,38 +28,47 @@ fn error_eof(expected: &str) -> ParseError {
     }
 }
 
-pub fn parse_program(tokens: Vec<Token>) -> Result<Program, ParseError> {
+/// Parses the whole program, after an error it skips to the next statement or declaration
+/// so a single run reports every error it can find.
+pub fn parse_program(tokens: Vec<Token>) -> Result<Program, Vec<ParseError>> {
     let mut p = Program {
         functions: Vec::new(),
         globals: Vec::new(),
+        structs: Vec::new(),
+        enums: Vec::new(),
     };
 
     let mut tokens = tokens.iter().peekable();
+    let mut errors = Vec::new();
 
     loop {
         skip_whitespace(&mut tokens);
-        if let None = tokens.peek() {
+        if tokens.peek().is_none() {
             break;
         }
         if let Some(TT::EOF) = tokens.peek().map(|t| &t.token_type) {
             break;
         }
 
-        let t = tokens.peek().ok_or(error_eof("function or EOF"))?;
-        match t.token_type {
-            TT::Keyword(KW::Fn) => {
-                let f = parse_function(&mut tokens)?;
-                p.functions.push(f);
-            }
-            TT::Keyword(KW::Global) => {
-                let g = parse_global(&mut tokens)?;
-                p.globals.push(g);
-            }
-            _ => return error("function or global", t),
+        let t = tokens.peek().unwrap();
+        let parsed = match t.token_type {
+            TT::Keyword(KW::Fn) => parse_function(&mut tokens, &mut errors).map(|f| p.functions.push(f)),
+            TT::Keyword(KW::Global) => parse_global(&mut tokens).map(|g| p.globals.push(g)),
+            TT::Keyword(KW::Struct) => parse_struct(&mut tokens).map(|s| p.structs.push(s)),
+            TT::Keyword(KW::Enum) => parse_enum(&mut tokens).map(|e| p.enums.push(e)),
+            _ => error("function, global, struct or enum", t),
+        };
+        if let Err(e) = parsed {
+            errors.push(e);
+            skip_to_declaration(&mut tokens);
         }
     }
 
-    Ok(p)
+    if errors.is_empty() {
+        Ok(p)
+    } else {
+        Err(errors)
+    }
 }
 
 fn parse_global(ti: &mut TI<'_>) -> Result<Global, ParseError> {
@@ -81,7 +91,99 @@ fn parse_global(ti: &mut TI<'_>) -> Result<Global, ParseError> {
     Ok(Global { name, value, ttype, fi: sfi.merge(&efi)})
 }
 
-fn parse_function(ti: &mut TI<'_>) -> Result<Function, ParseError> {
+/// `struct Name { field: type, ... }`, a trailing comma is allowed.
+fn parse_struct(ti: &mut TI<'_>) -> Result<Struct, ParseError> {
+    let sfi = expect(ti, TT::Keyword(KW::Struct), "struct")?;
+
+    skip_whitespace(ti);
+    let t = ti.next().ok_or(error_eof("struct name"))?;
+    let name = match t.token_type {
+        TT::Ident(ref s) => s.clone(),
+        _ => return error("struct name", t),
+    };
+
+    expect_sk_ws(ti, TT::LBrace, "{")?;
+    let mut fields = Vec::new();
+    let efi = loop {
+        skip_whitespace(ti);
+        let t = ti.next().ok_or(error_eof("field name or }"))?;
+        let field_name = match t.token_type {
+            TT::Ident(ref s) => s.clone(),
+            TT::RBrace => break t.fi,
+            _ => return error("field name or }", t),
+        };
+        expect_sk_ws(ti, TT::Colon, ":")?;
+        let ttype = parse_type(ti)?;
+        fields.push(StructField { name: field_name, fi: t.fi.merge(&ttype.fi()), ttype });
+
+        skip_whitespace(ti);
+        let t = ti.next().ok_or(error_eof("}"))?;
+        match t.token_type {
+            TT::RBrace => break t.fi,
+            TT::Comma => continue,
+            _ => return error("comma or }", t),
+        }
+    };
+
+    Ok(Struct { name, fields, fi: sfi.merge(&efi) })
+}
+
+/// `enum Name { Variant, Variant(type, ...), ... }`, a trailing comma is allowed.
+fn parse_enum(ti: &mut TI<'_>) -> Result<Enum, ParseError> {
+    let sfi = expect(ti, TT::Keyword(KW::Enum), "enum")?;
+
+    skip_whitespace(ti);
+    let t = ti.next().ok_or(error_eof("enum name"))?;
+    let name = match t.token_type {
+        TT::Ident(ref s) => s.clone(),
+        _ => return error("enum name", t),
+    };
+
+    expect_sk_ws(ti, TT::LBrace, "{")?;
+    let mut variants = Vec::new();
+    let efi = loop {
+        skip_whitespace(ti);
+        let t = ti.next().ok_or(error_eof("variant name or }"))?;
+        let variant_name = match t.token_type {
+            TT::Ident(ref s) => s.clone(),
+            TT::RBrace => break t.fi,
+            _ => return error("variant name or }", t),
+        };
+
+        let mut payload = Vec::new();
+        let mut variant_fi = t.fi;
+        skip_whitespace(ti);
+        if let Some(TT::LParen) = ti.peek().map(|t| &t.token_type) {
+            ti.next();
+            loop {
+                payload.push(parse_type(ti)?);
+                skip_whitespace(ti);
+                let t = ti.next().ok_or(error_eof(")"))?;
+                match t.token_type {
+                    TT::RParen => {
+                        variant_fi = variant_fi.merge(&t.fi);
+                        break;
+                    }
+                    TT::Comma => continue,
+                    _ => return error("comma or )", t),
+                }
+            }
+        }
+        variants.push(Variant { name: variant_name, payload, fi: variant_fi });
+
+        skip_whitespace(ti);
+        let t = ti.next().ok_or(error_eof("}"))?;
+        match t.token_type {
+            TT::RBrace => break t.fi,
+            TT::Comma => continue,
+            _ => return error("comma or }", t),
+        }
+    };
+
+    Ok(Enum { name, variants, fi: sfi.merge(&efi) })
+}
+
+fn parse_function(ti: &mut TI<'_>, errors: &mut Vec<ParseError>) -> Result<Function, ParseError> {
     let sfi = expect(ti, TT::Keyword(KW::Fn), "fn")?;
 
     skip_whitespace(ti);
@@ -96,7 +198,7 @@ fn parse_function(ti: &mut TI<'_>) -> Result<Function, ParseError> {
     expect_sk_ws(ti, TT::ReturnArrow, "->")?;
     let ret_type = parse_type(ti)?;
 
-    let (body, efi) = parse_block(ti)?;
+    let (body, efi) = parse_block(ti, errors)?;
 
     Ok(Function {
         name,
@@ -107,23 +209,21 @@ fn parse_function(ti: &mut TI<'_>) -> Result<Function, ParseError> {
     })
 }
 
-fn parse_block(ti: &mut TI<'_>) -> Result<(Vec<Statement>, FI), ParseError> {
+fn parse_block(ti: &mut TI<'_>, errors: &mut Vec<ParseError>) -> Result<(Vec<Statement>, FI), ParseError> {
     let mut stmts = Vec::new();
     let sfi = expect_sk_ws(ti, TT::LBrace, "{")?;
 
     loop {
         skip_whitespace(ti);
         let t = ti.peek().ok_or(error_eof("statement or }"))?;
-        match t.token_type {
-            TT::Keyword(KW::Let) => stmts.push(Stmt::Let(parse_let(ti)?)),
-            TT::Keyword(KW::Return) => stmts.push(Stmt::Return(parse_return(ti)?)),
-            TT::Keyword(KW::If) => stmts.push(Stmt::If(parse_if(ti)?)),
-            TT::Keyword(KW::While) => stmts.push(Stmt::While(parse_while(ti)?)),
-            TT::Keyword(KW::Do) => stmts.push(Stmt::DoWhile(parse_do_while(ti)?)),
-            TT::Ident(_) => stmts.push(parse_ident_start_statement(ti)?),
-            TT::Keyword(KW::ASM) => stmts.push(Stmt::Asm(parse_asm(ti)?)),
-            TT::RBrace => break,
-            _ => return error("statement", t),
+        if t.token_type == TT::RBrace {
+            break;
+        }
+        match parse_statement(ti, errors) {
+            Ok(stmt) => stmts.push(stmt),
+            // the rest of the statement is skipped, the block carries on with the next one
+            Err(e) if skip_statement(ti, &e) => errors.push(e),
+            Err(e) => return Err(e),
         }
     }
 
@@ -132,6 +232,23 @@ fn parse_block(ti: &mut TI<'_>) -> Result<(Vec<Statement>, FI), ParseError> {
     Ok((stmts, sfi.merge(&efi)))
 }
 
+fn parse_statement(ti: &mut TI<'_>, errors: &mut Vec<ParseError>) -> Result<Statement, ParseError> {
+    let t = ti.peek().ok_or(error_eof("statement"))?;
+    match t.token_type {
+        TT::Keyword(KW::Let) => Ok(Stmt::Let(parse_let(ti)?)),
+        TT::Keyword(KW::Return) => Ok(Stmt::Return(parse_return(ti)?)),
+        TT::Keyword(KW::If) => Ok(Stmt::If(parse_if(ti, errors)?)),
+        TT::Keyword(KW::While) => Ok(Stmt::While(parse_while(ti, errors)?)),
+        TT::Keyword(KW::Do) => Ok(Stmt::DoWhile(parse_do_while(ti, errors)?)),
+        TT::Keyword(KW::For) => Ok(Stmt::For(Box::new(parse_for(ti, errors)?))),
+        TT::Keyword(KW::Match) => Ok(Stmt::Match(parse_match(ti, errors)?)),
+        TT::Keyword(KW::Break) | TT::Keyword(KW::Continue) => parse_loop_control(ti),
+        TT::Ident(_) | TT::Asterisk | TT::LParen => parse_expression_statement(ti),
+        TT::Keyword(KW::ASM) => Ok(Stmt::Asm(parse_asm(ti)?)),
+        _ => error("statement", t),
+    }
+}
+
 fn parse_asm(ti: &mut TI<'_>) -> Result<Asm, ParseError> {
     let sfi = expect(ti, TT::Keyword(KW::ASM), "asm")?;
     expect_sk_ws(ti, TT::LBrace, "{")?;
@@ -182,10 +299,10 @@ fn parse_asm(ti: &mut TI<'_>) -> Result<Asm, ParseError> {
     Ok(Asm { segments, fi: sfi.merge(&efi) })
 }
 
-fn parse_do_while(ti: &mut TI<'_>) -> Result<DoWhile, ParseError> {
+fn parse_do_while(ti: &mut TI<'_>, errors: &mut Vec<ParseError>) -> Result<DoWhile, ParseError> {
     let sfi = expect(ti, TT::Keyword(KW::Do), "do")?;
 
-    let (body, _) = parse_block(ti)?;
+    let (body, _) = parse_block(ti, errors)?;
 
     expect_sk_ws(ti, TT::Keyword(KW::While), "while")?;
     expect_sk_ws(ti, TT::LParen, "(")?;
@@ -198,7 +315,7 @@ fn parse_do_while(ti: &mut TI<'_>) -> Result<DoWhile, ParseError> {
     Ok(DoWhile { condition, body, fi: sfi.merge(&efi)})
 }
 
-fn parse_while(ti: &mut TI<'_>) -> Result<While, ParseError> {
+fn parse_while(ti: &mut TI<'_>, errors: &mut Vec<ParseError>) -> Result<While, ParseError> {
     let sfi = expect(ti, TT::Keyword(KW::While), "while")?;
     expect_sk_ws(ti, TT::LParen, "(")?;
 
@@ -206,12 +323,46 @@ fn parse_while(ti: &mut TI<'_>) -> Result<While, ParseError> {
 
     expect_sk_ws(ti, TT::RParen, ")")?;
 
-    let (body, efi) = parse_block(ti)?;
+    let (body, efi) = parse_block(ti, errors)?;
 
     Ok(While { condition, body, fi: sfi.merge(&efi)})
 }
 
-fn parse_if(ti: &mut TI<'_>) -> Result<If, ParseError> {
+fn parse_for(ti: &mut TI<'_>, errors: &mut Vec<ParseError>) -> Result<For, ParseError> {
+    let sfi = expect(ti, TT::Keyword(KW::For), "for")?;
+    expect_sk_ws(ti, TT::LParen, "(")?;
+
+    // init, declares the loop variable: `i: u64 = 0;`
+    skip_whitespace(ti);
+    let t = ti.next().ok_or(error_eof("identifier"))?;
+    let name = match t.token_type {
+        TT::Ident(ref s) => s.clone(),
+        _ => return error("identifier", t),
+    };
+    expect_sk_ws(ti, TT::Colon, ":")?;
+    let ttype = parse_type(ti)?;
+    expect_sk_ws(ti, TT::Assign, "=")?;
+    let value = parse_expression(ti, Precedence::Lowest)?;
+    let init_efi = expect_sk_ws(ti, TT::Semicolon, ";")?;
+    let init = Let { name, ttype, value, fi: t.fi.merge(&init_efi)};
+
+    let condition = parse_expression(ti, Precedence::Lowest)?;
+    expect_sk_ws(ti, TT::Semicolon, ";")?;
+
+    // step, an assignment without the `;`: `i = i + 1`
+    let target = parse_expression(ti, Precedence::Lowest)?;
+    expect_sk_ws(ti, TT::Assign, "=")?;
+    let value = parse_expression(ti, Precedence::Lowest)?;
+    let step = Assign { fi: target.fi().merge(&value.fi()), target, value };
+
+    expect_sk_ws(ti, TT::RParen, ")")?;
+
+    let (body, efi) = parse_block(ti, errors)?;
+
+    Ok(For { init, condition, step, body, fi: sfi.merge(&efi)})
+}
+
+fn parse_if(ti: &mut TI<'_>, errors: &mut Vec<ParseError>) -> Result<If, ParseError> {
     let sfi = expect(ti, TT::Keyword(KW::If), "if")?;
     expect_sk_ws(ti, TT::LParen, "(")?;
 
@@ -219,7 +370,7 @@ fn parse_if(ti: &mut TI<'_>) -> Result<If, ParseError> {
 
     expect_sk_ws(ti, TT::RParen, ")")?;
 
-    let (body, mut efi) = parse_block(ti)?;
+    let (body, mut efi) = parse_block(ti, errors)?;
 
     let mut else_body = Vec::new();
 
@@ -227,7 +378,15 @@ fn parse_if(ti: &mut TI<'_>) -> Result<If, ParseError> {
     if let Some(t) = ti.peek() {
         if t.token_type == TT::Keyword(KW::Else) {
             ti.next();
-            (else_body, efi) = parse_block(ti)?;
+            skip_whitespace(ti);
+            // `else if` is an else body holding a single if statement
+            if let Some(TT::Keyword(KW::If)) = ti.peek().map(|t| &t.token_type) {
+                let else_if = parse_if(ti, errors)?;
+                efi = else_if.fi;
+                else_body = vec![Stmt::If(else_if)];
+            } else {
+                (else_body, efi) = parse_block(ti, errors)?;
+            }
         }
     }
 
@@ -239,6 +398,67 @@ fn parse_if(ti: &mut TI<'_>) -> Result<If, ParseError> {
     })
 }
 
+/// `match (value) { Circle(r) => { ... } Empty => { ... } _ => { ... } }`
+fn parse_match(ti: &mut TI<'_>, errors: &mut Vec<ParseError>) -> Result<Match, ParseError> {
+    let sfi = expect(ti, TT::Keyword(KW::Match), "match")?;
+    expect_sk_ws(ti, TT::LParen, "(")?;
+
+    let value = parse_expression(ti, Precedence::Lowest)?;
+
+    expect_sk_ws(ti, TT::RParen, ")")?;
+    expect_sk_ws(ti, TT::LBrace, "{")?;
+
+    let mut arms = Vec::new();
+    let efi = loop {
+        skip_whitespace(ti);
+        let t = ti.next().ok_or(error_eof("variant name or }"))?;
+        let variant = match t.token_type {
+            TT::Ident(ref s) if s == "_" => None,
+            TT::Ident(ref s) => Some(s.clone()),
+            TT::RBrace => break t.fi,
+            _ => return error("variant name or }", t),
+        };
+
+        let mut bindings = Vec::new();
+        skip_whitespace(ti);
+        if variant.is_some() && matches!(ti.peek().map(|t| &t.token_type), Some(TT::LParen)) {
+            ti.next();
+            loop {
+                skip_whitespace(ti);
+                let t = ti.next().ok_or(error_eof("binding name"))?;
+                match t.token_type {
+                    TT::Ident(ref s) => bindings.push(s.clone()),
+                    _ => return error("binding name", t),
+                }
+                skip_whitespace(ti);
+                let t = ti.next().ok_or(error_eof(")"))?;
+                match t.token_type {
+                    TT::RParen => break,
+                    TT::Comma => continue,
+                    _ => return error("comma or )", t),
+                }
+            }
+        }
+
+        expect_sk_ws(ti, TT::FatArrow, "=>")?;
+        let (body, body_fi) = parse_block(ti, errors)?;
+        arms.push(MatchArm { variant, bindings, body, fi: t.fi.merge(&body_fi) });
+    };
+
+    Ok(Match { value, arms, fi: sfi.merge(&efi) })
+}
+
+fn parse_loop_control(ti: &mut TI<'_>) -> Result<Statement, ParseError> {
+    let t = ti.next().ok_or(error_eof("break or continue"))?;
+    let efi = expect_sk_ws(ti, TT::Semicolon, ";")?;
+    let fi = t.fi.merge(&efi);
+    match t.token_type {
+        TT::Keyword(KW::Break) => Ok(Stmt::Break(fi)),
+        TT::Keyword(KW::Continue) => Ok(Stmt::Continue(fi)),
+        _ => error("break or continue", t),
+    }
+}
+
 fn parse_return(ti: &mut TI<'_>) -> Result<Return, ParseError> {
     let sfi = expect(ti, TT::Keyword(KW::Return), "return")?;
 
@@ -248,27 +468,21 @@ fn parse_return(ti: &mut TI<'_>) -> Result<Return, ParseError> {
     Ok(Return { value, fi: sfi.merge(&efi)})
 }
 
-fn parse_ident_start_statement(ti: &mut TI<'_>) -> Result<Statement, ParseError> {
-    let t = ti.next().ok_or(error_eof("identifier"))?;
-    let sfi = t.fi;
-    let name = match t.token_type {
-        TT::Ident(ref s) => s.clone(),
-        _ => return error("identifier", t),
-    };
+/// Assignments and calls, both start with an expression: `*p = 1;`, `a[i] = 2;`, `(*p)[0] = 3;`, `f(x);`
+fn parse_expression_statement(ti: &mut TI<'_>) -> Result<Statement, ParseError> {
+    let exp = parse_expression(ti, Precedence::Lowest)?;
 
     skip_whitespace(ti);
     let t = ti.peek().ok_or(error_eof("assignment or call"))?;
-    match t.token_type {
-        TT::Assign => {
+    match (t.token_type.clone(), exp) {
+        (TT::Assign, target) => {
             ti.next();
             let value = parse_expression(ti, Precedence::Lowest)?;
             let efi = expect_sk_ws(ti, TT::Semicolon, ";")?;
-            Ok(Stmt::Assign(Assign { name, value, fi: sfi.merge(&efi)}))
+            Ok(Stmt::Assign(Assign { fi: target.fi().merge(&efi), target, value }))
         }
-        TT::LParen => {
-            let efi = t.fi;
-            let call = parse_call(ti, name, efi)?;
-            expect_sk_ws(ti, TT::Semicolon, ";")?;
+        (TT::Semicolon, Exp::Call(call)) => {
+            ti.next();
             Ok(Stmt::Call(call))
         }
         _ => error("assignment or call", t),
@@ -301,6 +515,34 @@ fn parse_type(ti: &mut TI<'_>) -> Result<Type_, ParseError> {
     let ttype = match t.token_type {
         TT::Keyword(KW::U64) => Type_::U64(t.fi),
         TT::Keyword(KW::I64) => Type_::I64(t.fi),
+        TT::Keyword(KW::U32) => Type_::U32(t.fi),
+        TT::Keyword(KW::I32) => Type_::I32(t.fi),
+        TT::Keyword(KW::U16) => Type_::U16(t.fi),
+        TT::Keyword(KW::I16) => Type_::I16(t.fi),
+        TT::Keyword(KW::U8) => Type_::U8(t.fi),
+        TT::Keyword(KW::I8) => Type_::I8(t.fi),
+        TT::Keyword(KW::F64) => Type_::F64(t.fi),
+        TT::Keyword(KW::Bool) => Type_::Bool(t.fi),
+        TT::Keyword(KW::Str) => Type_::Str(t.fi),
+        TT::Ident(ref name) => Type_::Named(name.clone(), t.fi),
+        TT::Asterisk => {
+            let inner = parse_type(ti)?;
+            let fi = t.fi.merge(&inner.fi());
+            Type_::Ptr(Box::new(inner), fi)
+        }
+        TT::LBracket => {
+            let elem = parse_type(ti)?;
+            expect_sk_ws(ti, TT::Semicolon, ";")?;
+            skip_whitespace(ti);
+            let lt = ti.next().ok_or(error_eof("array length"))?;
+            let len = match lt.token_type {
+                TT::I64(n) if n > 0 => n as u64,
+                TT::U64(n) if n > 0 => n,
+                _ => return error("array length greater than zero", lt),
+            };
+            let efi = expect_sk_ws(ti, TT::RBracket, "]")?;
+            Type_::Array(Box::new(elem), len, t.fi.merge(&efi))
+        }
         _ => return error("type", t),
     };
     Ok(ttype)
@@ -311,8 +553,37 @@ fn parse_expression(ti: &mut TI<'_>, prec: Precedence) -> Result<Exp, ParseError
     let t = ti.next().ok_or(error_eof("expression"))?;
     let sfi = t.fi;
     let mut exp = match t.token_type {
+        _ if signed_min(&t.token_type, sfi).is_some() => return error("number that fits in its type", t),
         TT::U64(n) => Exp::U64(n, sfi),
         TT::I64(n) => Exp::I64(n, sfi),
+        TT::U32(n) => Exp::U32(n, sfi),
+        TT::I32(n) => Exp::I32(n, sfi),
+        TT::U16(n) => Exp::U16(n, sfi),
+        TT::I16(n) => Exp::I16(n, sfi),
+        TT::U8(n) => Exp::U8(n, sfi),
+        TT::I8(n) => Exp::I8(n, sfi),
+        TT::F64(n) => Exp::F64(n, sfi),
+        TT::Keyword(KW::True) => Exp::Bool(true, sfi),
+        TT::Keyword(KW::False) => Exp::Bool(false, sfi),
+        TT::Str(ref bytes) => Exp::Str(bytes.clone(), sfi),
+        TT::Char(byte) => Exp::U8(byte, sfi),
+        TT::Bang => unary_op(UnOp::Not, parse_expression(ti, Precedence::Prefix)?, sfi),
+        TT::Tilde => unary_op(UnOp::BitNot, parse_expression(ti, Precedence::Prefix)?, sfi),
+        TT::Minus => match ti.peek().and_then(|lit| signed_min(&lit.token_type, sfi.merge(&lit.fi))) {
+            Some(min) => {
+                ti.next();
+                min
+            }
+            None => unary_op(UnOp::Neg, parse_expression(ti, Precedence::Prefix)?, sfi),
+        },
+        TT::Asterisk => unary_op(UnOp::Deref, parse_expression(ti, Precedence::Prefix)?, sfi),
+        TT::Ampersand => unary_op(UnOp::AddrOf, parse_expression(ti, Precedence::Prefix)?, sfi),
+        TT::LParen => {
+            let inner = parse_expression(ti, Precedence::Lowest)?;
+            let efi = expect_sk_ws(ti, TT::RParen, ")")?;
+            inner.with_fi(sfi.merge(&efi))
+        }
+        TT::LBracket => parse_array_literal(ti, sfi)?,
         TT::Ident(ref s) => parse_ident_start_expression(ti, s.clone(), sfi)?,
         _ => return error("expression", t),
     };
@@ -334,6 +605,37 @@ fn parse_expression(ti: &mut TI<'_>, prec: Precedence) -> Result<Exp, ParseError
             TT::NotEq => exp = binop(exp, Op::Ne, parse_expression(ti, next_prec)?),
             TT::Lt => exp = binop(exp, Op::LT, parse_expression(ti, next_prec)?),
             TT::Gt => exp = binop(exp, Op::GT, parse_expression(ti, next_prec)?),
+            TT::LtEq => exp = binop(exp, Op::LE, parse_expression(ti, next_prec)?),
+            TT::GtEq => exp = binop(exp, Op::GE, parse_expression(ti, next_prec)?),
+            TT::And => exp = binop(exp, Op::And, parse_expression(ti, next_prec)?),
+            TT::Or => exp = binop(exp, Op::Or, parse_expression(ti, next_prec)?),
+            TT::Ampersand => exp = binop(exp, Op::BitAnd, parse_expression(ti, next_prec)?),
+            TT::Pipe => exp = binop(exp, Op::BitOr, parse_expression(ti, next_prec)?),
+            TT::Caret => exp = binop(exp, Op::BitXor, parse_expression(ti, next_prec)?),
+            TT::ShiftLeft => exp = binop(exp, Op::Shl, parse_expression(ti, next_prec)?),
+            TT::ShiftRight => exp = binop(exp, Op::Shr, parse_expression(ti, next_prec)?),
+            TT::LBracket => {
+                let index = parse_expression(ti, Precedence::Lowest)?;
+                let efi = expect_sk_ws(ti, TT::RBracket, "]")?;
+                let fi = exp.fi().merge(&efi);
+                exp = Exp::Index(Box::new(exp), Box::new(index), fi);
+                skip_whitespace(ti);
+            }
+            TT::Dot => {
+                let t = ti.next().ok_or(error_eof("field name"))?;
+                let TT::Ident(ref name) = t.token_type else {
+                    return error("field name", t);
+                };
+                let fi = exp.fi().merge(&t.fi);
+                exp = Exp::Field(Box::new(exp), name.clone(), fi);
+                skip_whitespace(ti);
+            }
+            TT::Keyword(KW::As) => {
+                let ttype = parse_type(ti)?;
+                let fi = exp.fi().merge(&ttype.fi());
+                exp = Exp::Cast(Box::new(exp), ttype, fi);
+                skip_whitespace(ti);
+            }
             TT::Semicolon | TT::EOF => break,
             TT::Comma | TT::RParen => break, // expressions can appear as arguments to function calls
             _ => return error("operator or ;", t),
@@ -351,10 +653,88 @@ fn parse_ident_start_expression(ti: &mut TI<'_>, name: String, sfi: FI) -> Resul
             let call = parse_call(ti, name, sfi)?;
             Ok(Exp::Call(call))
         }
+        TT::LBrace => parse_struct_literal(ti, name, sfi),
+        TT::DoubleColon => parse_variant_literal(ti, name, sfi),
         _ => Ok(Exp::Var(name, sfi)),
     }
 }
 
+/// `Shape::Circle(1.5)` or `Shape::Empty`, the enum name is already consumed.
+fn parse_variant_literal(ti: &mut TI<'_>, name: String, sfi: FI) -> Result<Exp, ParseError> {
+    expect(ti, TT::DoubleColon, "::")?;
+    skip_whitespace(ti);
+    let t = ti.next().ok_or(error_eof("variant name"))?;
+    let TT::Ident(ref variant) = t.token_type else {
+        return error("variant name", t);
+    };
+
+    skip_whitespace(ti);
+    match ti.peek().map(|t| &t.token_type) {
+        // the payload is parsed like call arguments
+        Some(TT::LParen) => {
+            let call = parse_call(ti, variant.clone(), t.fi)?;
+            Ok(Exp::Variant(name, call.name, call.args, sfi.merge(&call.fi)))
+        }
+        _ => Ok(Exp::Variant(name, variant.clone(), Vec::new(), sfi.merge(&t.fi))),
+    }
+}
+
+/// `[1, 2, 3]`, the opening bracket is already consumed.
+fn parse_array_literal(ti: &mut TI<'_>, sfi: FI) -> Result<Exp, ParseError> {
+    let mut elements = Vec::new();
+
+    let efi = loop {
+        skip_whitespace(ti);
+        let t = ti.peek().ok_or(error_eof("element or ]"))?;
+        let element = match t.token_type {
+            TT::RBracket => {
+                let efi = t.fi;
+                ti.next();
+                break efi;
+            }
+            _ => parse_expression(ti, Precedence::Lowest)?,
+        };
+        elements.push(element);
+
+        let t = ti.next().ok_or(error_eof("]"))?;
+        match t.token_type {
+            TT::RBracket => break t.fi,
+            TT::Comma => continue,
+            _ => return error("comma or ]", t),
+        }
+    };
+
+    Ok(Exp::Array(elements, sfi.merge(&efi)))
+}
+
+/// `Point { x: 1, y: 2 }`, the name is already consumed.
+fn parse_struct_literal(ti: &mut TI<'_>, name: String, sfi: FI) -> Result<Exp, ParseError> {
+    expect(ti, TT::LBrace, "{")?;
+    let mut fields = Vec::new();
+
+    let efi = loop {
+        skip_whitespace(ti);
+        let t = ti.next().ok_or(error_eof("field name or }"))?;
+        let field_name = match t.token_type {
+            TT::Ident(ref s) => s.clone(),
+            TT::RBrace => break t.fi,
+            _ => return error("field name or }", t),
+        };
+        expect_sk_ws(ti, TT::Colon, ":")?;
+        fields.push((field_name, parse_expression(ti, Precedence::Lowest)?));
+
+        skip_whitespace(ti);
+        let t = ti.next().ok_or(error_eof("}"))?;
+        match t.token_type {
+            TT::RBrace => break t.fi,
+            TT::Comma => continue,
+            _ => return error("comma or }", t),
+        }
+    };
+
+    Ok(Exp::Struct(name, fields, sfi.merge(&efi)))
+}
+
 fn parse_call(ti: &mut TI<'_>, name: String, sfi: FI) -> Result<Call, ParseError> {
     let mut args = Vec::new();
     expect(ti, TT::LParen, "(")?;
@@ -413,7 +793,7 @@ fn parse_param(ti: &mut TI<'_>, name: String, sfi: FI) -> Result<Parameter, Pars
 
     let ttype = parse_type(ti)?;
 
-    Ok(Parameter { name, ttype, fi: sfi.merge(&ttype.fi())})
+    Ok(Parameter { name, fi: sfi.merge(&ttype.fi()), ttype })
 }
 
 fn expect(ti: &mut TI<'_>, expected: TT, msg: &str) -> Result<FI, ParseError> {
@@ -424,11 +804,65 @@ fn expect(ti: &mut TI<'_>, expected: TT, msg: &str) -> Result<FI, ParseError> {
     Ok(t.fi)
 }
 
+/// The tokenizer lets the magnitude of a signed minimum through as the minimum, so `-128i8`
+/// is a single literal, this is the literal when the token is one.
+fn signed_min(tt: &TT, fi: FI) -> Option<Exp> {
+    match *tt {
+        TT::I64(n) if n < 0 => Some(Exp::I64(n, fi)),
+        TT::I32(n) if n < 0 => Some(Exp::I32(n, fi)),
+        TT::I16(n) if n < 0 => Some(Exp::I16(n, fi)),
+        TT::I8(n) if n < 0 => Some(Exp::I8(n, fi)),
+        _ => None,
+    }
+}
+
 fn expect_sk_ws(ti: &mut TI<'_>, expected: TT, msg: &str) -> Result<FI, ParseError> {
     skip_whitespace(ti);
     expect(ti, expected, msg)
 }
 
+/// Skips what is left of a statement after an error: up to its `;`, past the block it opened,
+/// or up to the `}` closing the enclosing block. Returns false when it runs into the next
+/// top level declaration or the end of the file instead.
+fn skip_statement(ti: &mut TI<'_>, error: &ParseError) -> bool {
+    // the statement is already over when the error took its `;`
+    skip_whitespace(ti);
+    let consumed = ti.peek().is_none_or(|t| t.fi != error.fi());
+    if consumed && error.token.token_type == TT::Semicolon {
+        return true;
+    }
+    let mut depth = 0;
+    while let Some(t) = ti.peek() {
+        match t.token_type {
+            TT::Semicolon if depth == 0 => {
+                ti.next();
+                return true;
+            }
+            TT::RBrace if depth == 0 => return true,
+            TT::RBrace if depth == 1 => {
+                ti.next();
+                return true;
+            }
+            TT::RBrace => depth -= 1,
+            TT::LBrace => depth += 1,
+            TT::Keyword(KW::Fn | KW::Global | KW::Struct | KW::Enum) | TT::EOF => return false,
+            _ => {}
+        }
+        ti.next();
+    }
+    false
+}
+
+/// Skips to the next top level declaration after an error.
+fn skip_to_declaration(ti: &mut TI<'_>) {
+    while let Some(t) = ti.peek() {
+        if let TT::Keyword(KW::Fn | KW::Global | KW::Struct | KW::Enum) | TT::EOF = t.token_type {
+            break;
+        }
+        ti.next();
+    }
+}
+
 fn skip_whitespace(ti: &mut TI<'_>) {
     while let Some(t) = ti.peek() {
         match t.token_type {
@@ -440,33 +874,50 @@ fn skip_whitespace(ti: &mut TI<'_>) {
 }
 
 impl ParseError {
-    pub fn pretty_print(&self, input: &str) {
-        let out_str = underline_error(input, &self.token.fi);
-        println!("{}", out_str);
-        println!(
-            "Error: expected `{}` but found {:?}",
-            self.expected, self.token.token_type
-        );
-        println!();
+    pub fn fi(&self) -> FI {
+        self.token.fi
+    }
+    pub fn diagnostic(&self) -> Box<Diagnostic> {
+        let found = match self.token.token_type {
+            TT::EOF => "the end of the file".to_string(),
+            TT::Newline => "a new line".to_string(),
+            ref tt => format!("`{}`", tt.string()),
+        };
+        Diagnostic::new(&format!("expected `{}`, found {}", self.expected, found), self.token.fi)
+            .with_label(&format!("expected `{}` here", self.expected))
     }
 }
 
 #[derive(Debug, PartialEq, PartialOrd)]
 enum Precedence {
     Lowest,
+    LogicalOr,
+    LogicalAnd,
     Comparison,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
     Sum,
     Product,
+    Cast,
     Prefix,
     Call,
 }
 
 fn precedence(t: &Token) -> Precedence {
     match t.token_type {
-        TT::Eq | TT::NotEq | TT::Lt | TT::Gt => Precedence::Comparison,
+        TT::Or => Precedence::LogicalOr,
+        TT::And => Precedence::LogicalAnd,
+        TT::Eq | TT::NotEq | TT::Lt | TT::Gt | TT::LtEq | TT::GtEq => Precedence::Comparison,
+        TT::Pipe => Precedence::BitOr,
+        TT::Caret => Precedence::BitXor,
+        TT::Ampersand => Precedence::BitAnd,
+        TT::ShiftLeft | TT::ShiftRight => Precedence::Shift,
         TT::Plus | TT::Minus => Precedence::Sum,
         TT::Asterisk | TT::Slash | TT::Percent => Precedence::Product,
-        TT::LParen => Precedence::Call,
+        TT::Keyword(KW::As) => Precedence::Cast,
+        TT::LParen | TT::LBracket | TT::Dot => Precedence::Call,
         _ => Precedence::Lowest,
     }
 }
@@ -474,6 +925,7 @@ fn precedence(t: &Token) -> Precedence {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::file_info::SourceMap;
     use crate::tokenizer::*;
     use pretty_assertions::assert_eq;
 
@@ -481,9 +933,11 @@ mod test {
 
     #[test]
     fn test_parse_program() {
-        let tokens = tokenize("fn main() -> u64 { let x: i64 = 42 + 1; }");
+        let tokens = tokenize("fn main() -> u64 { let x: i64 = 42 + 1; }", 0).unwrap();
         let expected = Program {
             globals: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
             functions: vec![Function {
                 name: "main".to_string(),
                 params: Vec::new(),
@@ -503,6 +957,12 @@ mod test {
         assert_eq!(p, Ok(expected));
     }
 
+    fn sources(input: &str) -> SourceMap {
+        let mut sources = SourceMap::default();
+        sources.add("test", input.to_string());
+        sources
+    }
+
     // helper functions to make the tests more concise
     fn add(x: Exp, y: Exp) -> Exp {
         binop(x, Op::Add, y)
@@ -534,17 +994,114 @@ mod test {
     fn gt(x: Exp, y: Exp) -> Exp {
         binop(x, Op::GT, y)
     }
+    fn le(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::LE, y)
+    }
+    fn ge(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::GE, y)
+    }
+
+    fn boolz(b: bool) -> Exp {
+        Exp::Bool(b, FI::zero())
+    }
+    fn var(name: &str) -> Exp {
+        Exp::Var(name.to_string(), FI::zero())
+    }
+    fn and(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::And, y)
+    }
+    fn or(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::Or, y)
+    }
+    fn not(x: Exp) -> Exp {
+        unary_op(UnOp::Not, x, FI::zero())
+    }
+    fn bit_and(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::BitAnd, y)
+    }
+    fn bit_or(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::BitOr, y)
+    }
+    fn bit_xor(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::BitXor, y)
+    }
+    fn shl(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::Shl, y)
+    }
+    fn shr(x: Exp, y: Exp) -> Exp {
+        binop(x, Op::Shr, y)
+    }
+    fn bit_not(x: Exp) -> Exp {
+        unary_op(UnOp::BitNot, x, FI::zero())
+    }
+    fn neg(x: Exp) -> Exp {
+        unary_op(UnOp::Neg, x, FI::zero())
+    }
+    fn deref(x: Exp) -> Exp {
+        unary_op(UnOp::Deref, x, FI::zero())
+    }
+    fn addr_of(x: Exp) -> Exp {
+        unary_op(UnOp::AddrOf, x, FI::zero())
+    }
+    fn field(base: Exp, name: &str) -> Exp {
+        Exp::Field(Box::new(base), name.to_string(), FI::zero())
+    }
+    fn cast(x: Exp, ttype: Type_) -> Exp {
+        Exp::Cast(Box::new(x), ttype, FI::zero())
+    }
+    fn index(array: Exp, i: Exp) -> Exp {
+        Exp::Index(Box::new(array), Box::new(i), FI::zero())
+    }
 
     fn zero_out(exp: &mut Exp) {
         match exp {
             Exp::U64(_, ref mut fi) => *fi = FI::zero(),
             Exp::I64(_, ref mut fi) => *fi = FI::zero(),
+            Exp::U32(_, ref mut fi) => *fi = FI::zero(),
+            Exp::I32(_, ref mut fi) => *fi = FI::zero(),
+            Exp::U16(_, ref mut fi) => *fi = FI::zero(),
+            Exp::I16(_, ref mut fi) => *fi = FI::zero(),
+            Exp::U8(_, ref mut fi) => *fi = FI::zero(),
+            Exp::I8(_, ref mut fi) => *fi = FI::zero(),
+            Exp::F64(_, ref mut fi) => *fi = FI::zero(),
+            Exp::Bool(_, ref mut fi) => *fi = FI::zero(),
+            Exp::Str(_, ref mut fi) => *fi = FI::zero(),
             Exp::Var(_, ref mut fi) => *fi = FI::zero(),
             Exp::BinOp(le, _, re, ref mut fi) => {
                 *fi = FI::zero();
                 zero_out(le);
                 zero_out(re);
             },
+            Exp::UnaryOp(_, e, ref mut fi) => {
+                *fi = FI::zero();
+                zero_out(e);
+            },
+            Exp::Index(array, index, ref mut fi) => {
+                *fi = FI::zero();
+                zero_out(array);
+                zero_out(index);
+            },
+            Exp::Array(elements, ref mut fi) => {
+                *fi = FI::zero();
+                elements.iter_mut().for_each(zero_out);
+            },
+            Exp::Struct(_, fields, ref mut fi) => {
+                *fi = FI::zero();
+                fields.iter_mut().for_each(|(_, value)| zero_out(value));
+            },
+            Exp::Variant(_, _, payload, ref mut fi) => {
+                *fi = FI::zero();
+                payload.iter_mut().for_each(zero_out);
+            },
+            Exp::Field(base, _, ref mut fi) => {
+                *fi = FI::zero();
+                zero_out(base);
+            },
+            Exp::Cast(e, ttype, ref mut fi) => {
+                *fi = FI::zero();
+                *ttype = ttype.zero();
+                zero_out(e);
+            },
             Exp::Call(ref mut call) => {
                 call.fi = FI::zero();
                 call.args.iter_mut().for_each(zero_out);
@@ -600,16 +1157,123 @@ mod test {
                     add(intz(4), mul(intz(5), intz(6))),
                 ),
             },
+            Test {
+                input: "42 + 1 <= 4 * 5",
+                expected: le(add(intz(42), intz(1)), mul(intz(4), intz(5))),
+            },
+            Test {
+                input: "42 >= 4 - 5",
+                expected: ge(intz(42), sub(intz(4), intz(5))),
+            },
+            Test {
+                input: "1 < 2 == true",
+                expected: eq(lt(intz(1), intz(2)), boolz(true)),
+            },
+            Test {
+                input: "!a && b || c",
+                expected: or(and(not(var("a")), var("b")), var("c")),
+            },
+            Test {
+                input: "a || b && 1 < 2",
+                expected: or(var("a"), and(var("b"), lt(intz(1), intz(2)))),
+            },
+            Test {
+                input: "a | b ^ c & d",
+                expected: bit_or(var("a"), bit_xor(var("b"), bit_and(var("c"), var("d")))),
+            },
+            Test {
+                input: "1 << 2 + 3 >> 4",
+                expected: shr(shl(intz(1), add(intz(2), intz(3))), intz(4)),
+            },
+            Test {
+                input: "a & 255 == ~b",
+                expected: eq(bit_and(var("a"), intz(255)), bit_not(var("b"))),
+            },
+            Test {
+                input: "-a * -3 - -b",
+                expected: sub(mul(neg(var("a")), neg(intz(3))), neg(var("b"))),
+            },
+            Test {
+                input: "-f(1) + 2",
+                expected: add(neg(Exp::Call(Call { name: "f".to_string(), args: vec![intz(1)], fi: FI::zero() })), intz(2)),
+            },
+            Test {
+                input: "(42 + 1) * 2",
+                expected: mul(add(intz(42), intz(1)), intz(2)),
+            },
+            Test {
+                input: "2 * (3 - (4 + 5)) / (a)",
+                expected: div(mul(intz(2), sub(intz(3), add(intz(4), intz(5)))), var("a")),
+            },
+            Test {
+                input: "-(a + b)",
+                expected: neg(add(var("a"), var("b"))),
+            },
+            Test {
+                input: "*p + *q * 2",
+                expected: add(deref(var("p")), mul(deref(var("q")), intz(2))),
+            },
+            Test {
+                input: "&a & **b",
+                expected: bit_and(addr_of(var("a")), deref(deref(var("b")))),
+            },
+            Test {
+                input: "*(p + 1)",
+                expected: deref(add(var("p"), intz(1))),
+            },
+            Test {
+                input: "s.len + \"a\\n\".ptr + m[0].len",
+                expected: add(add(field(var("s"), "len"), field(Exp::Str(b"a\n".to_vec(), FI::zero()), "ptr")), field(index(var("m"), intz(0)), "len")),
+            },
+            Test {
+                input: "c - 'a' + '\\n'",
+                expected: add(sub(var("c"), Exp::U8(b'a', FI::zero())), Exp::U8(b'\n', FI::zero())),
+            },
+            Test {
+                input: "-x as u8 * y",
+                expected: mul(cast(neg(var("x")), Type_::U8(FI::zero())), var("y")),
+            },
+            Test {
+                input: "a + 300u16 as i32 as u64",
+                expected: add(var("a"), cast(cast(Exp::U16(300, FI::zero()), Type_::I32(FI::zero())), Type_::U64(FI::zero()))),
+            },
+            Test {
+                input: "-1.5 * x as f64",
+                expected: mul(neg(Exp::F64(1.5, FI::zero())), cast(var("x"), Type_::F64(FI::zero()))),
+            },
+            Test {
+                input: "a[i + 1] * m[0][1]",
+                expected: mul(index(var("a"), add(var("i"), intz(1))), index(index(var("m"), intz(0)), intz(1))),
+            },
+            Test {
+                input: "*p[0] + [1, [a]]",
+                expected: add(deref(index(var("p"), intz(0))), Exp::Array(vec![intz(1), Exp::Array(vec![var("a")], FI::zero())], FI::zero())),
+            },
+            Test {
+                input: "!(a < b) && (c || d)",
+                expected: and(not(lt(var("a"), var("b"))), or(var("c"), var("d"))),
+            },
+            Test {
+                input: "f((1 + 2), (3)) * ((4))",
+                expected: mul(
+                    Exp::Call(Call { name: "f".to_string(), args: vec![add(intz(1), intz(2)), intz(3)], fi: FI::zero() }),
+                    intz(4),
+                ),
+            },
+            Test {
+                input: "!!a == b",
+                expected: eq(not(not(var("a"))), var("b")),
+            },
         ];
 
         for t in cases {
-            let tokens = tokenize(t.input);
+            let tokens = tokenize(t.input, 0).unwrap();
             let mut ti = tokens.iter().peekable();
             println!("input: {}", t.input);
             let e = parse_expression(&mut ti, Precedence::Lowest);
 
             if let Err(e) = e {
-                e.pretty_print(t.input);
+                e.diagnostic().pretty_print(&sources(t.input));
                 panic!("parse error");
             }
             let mut e = e.unwrap();
@@ -619,10 +1283,207 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_parse_parenthesized_spans() {
+        let input = "(1 + 2) * 3";
+        let tokens = tokenize(input, 0).unwrap();
+        let mut ti = tokens.iter().peekable();
+
+        let e = parse_expression(&mut ti, Precedence::Lowest);
+
+        let inner = binop(int(1, FI::new(1, 1)), Op::Add, int(2, FI::new(1, 5))).with_fi(FI::new(7, 0));
+        let expected = Exp::BinOp(Box::new(inner), Op::Mul, Box::new(int(3, FI::new(1, 10))), FI::new(11, 0));
+        assert_eq!(e, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_signed_min() {
+        let parse = |input: &str| {
+            let tokens = tokenize(input, 0).unwrap();
+            parse_expression(&mut tokens.iter().peekable(), Precedence::Lowest)
+        };
+        assert_eq!(parse("-128i8"), Ok(Exp::I8(i8::MIN, FI::new(6, 0))));
+        assert_eq!(parse("-9223372036854775808"), Ok(Exp::I64(i64::MIN, FI::new(20, 0))));
+        assert_eq!(
+            parse("-2147483648i32 * 2i32"),
+            Ok(Exp::BinOp(Box::new(Exp::I32(i32::MIN, FI::new(14, 0))), Op::Mul, Box::new(Exp::I32(2, FI::new(4, 17))), FI::new(21, 0)))
+        );
+        // the magnitude alone does not fit, here the `-` is a subtraction
+        assert!(parse("1 -2147483648i32").is_err());
+        let err = parse("- 128i8").unwrap_err();
+        assert_eq!((err.expected.as_str(), err.fi()), ("number that fits in its type", FI::new(5, 2)));
+        assert_eq!(err.diagnostic().msg, "expected `number that fits in its type`, found `128i8`");
+    }
+
+    #[test]
+    fn test_parse_pointers() {
+        let input = r#"fn main() -> u64 { let p: **u64 = &q; *p = 1; **p = 2; f(p); }"#;
+        let tokens = tokenize(input, 0).unwrap();
+        let prog = parse_program(tokens);
+        if let Err(errors) = prog {
+            errors.iter().for_each(|e| e.diagnostic().pretty_print(&sources(input)));
+            panic!("parse error");
+        }
+        let mut prog = prog.unwrap();
+        let body = &mut prog.functions[0].body;
+
+        let Stmt::Let(let_stmt) = &body[0] else { panic!("expected let, got {:?}", body[0]) };
+        assert_eq!(let_stmt.ttype.zero(), Type_::Ptr(Box::new(Type_::Ptr(Box::new(Type_::U64(FI::zero())), FI::zero())), FI::zero()));
+        assert_eq!(let_stmt.ttype.fi(), FI::new(5, 26));
+
+        for (stmt, target) in body[1..3].iter_mut().zip([deref(var("p")), deref(deref(var("p")))]) {
+            let Stmt::Assign(assign) = stmt else { panic!("expected assignment, got {:?}", stmt) };
+            zero_out(&mut assign.target);
+            assert_eq!(assign.target, target);
+        }
+        assert!(matches!(&body[3], Stmt::Call(call) if call.name == "f"));
+    }
+
+    #[test]
+    fn test_parse_struct() {
+        let input = "struct Node {\n  value: i64,\n  next: *Node,\n}\nfn main() -> u64 {\n  let n: Node = Node { value: 1, next: p };\n  (*n.next).value = n.value;\n}";
+        let mut prog = parse_program(tokenize(input, 0).unwrap()).unwrap();
+        assert_eq!(prog.structs.len(), 1);
+        let node = &prog.structs[0];
+        assert_eq!(node.name, "Node");
+        assert_eq!(node.fi, FI::new(44, 0));
+        let fields: Vec<(&str, Type_)> = node.fields.iter().map(|f| (f.name.as_str(), f.ttype.zero())).collect();
+        let node_type = Type_::Named("Node".to_string(), FI::zero());
+        assert_eq!(fields, vec![("value", Type_::I64(FI::zero())), ("next", Type_::Ptr(Box::new(node_type.clone()), FI::zero()))]);
+
+        let mut body = prog.functions.remove(0).body;
+        let Stmt::Let(let_stmt) = &body[0] else { panic!("expected a let, got {:?}", body[0]) };
+        assert_eq!(let_stmt.ttype.zero(), node_type);
+        let mut value = parse_expression(&mut tokenize("Node { value: 1, next: p }", 0).unwrap().iter().peekable(), Precedence::Lowest).unwrap();
+        assert_eq!(value.fi(), FI::new(26, 0));
+        zero_out(&mut value);
+        assert_eq!(value, Exp::Struct("Node".to_string(), vec![("value".to_string(), intz(1)), ("next".to_string(), var("p"))], FI::zero()));
+
+        let Stmt::Assign(assign) = body.remove(1) else { panic!("expected an assignment") };
+        let mut target = assign.target;
+        zero_out(&mut target);
+        assert_eq!(target, field(deref(field(var("n"), "next")), "value"));
+    }
+
+    #[test]
+    fn test_parse_enum_and_match() {
+        let input = "enum Shape {\n  Circle(f64),\n  Rect(i64, i64),\n  Empty,\n}\nfn main() -> u64 {\n  s = Shape::Rect(1, w);\n  match (s) {\n    Circle(_) => {}\n    Rect(w, h) => { return w; }\n    _ => {}\n  }\n}";
+        let mut prog = parse_program(tokenize(input, 0).unwrap()).unwrap();
+        assert_eq!(prog.enums.len(), 1);
+        let shape = &prog.enums[0];
+        assert_eq!(shape.name, "Shape");
+        let variants: Vec<(&str, Vec<Type_>)> = shape
+            .variants
+            .iter()
+            .map(|v| (v.name.as_str(), v.payload.iter().map(|t| t.zero()).collect()))
+            .collect();
+        assert_eq!(variants, vec![
+            ("Circle", vec![Type_::F64(FI::zero())]),
+            ("Rect", vec![Type_::I64(FI::zero()), Type_::I64(FI::zero())]),
+            ("Empty", vec![]),
+        ]);
+        assert_eq!(shape.variants[1].fi, FI::new(14, 30));
+
+        let mut body = prog.functions.remove(0).body;
+        let Stmt::Match(match_stmt) = body.remove(1) else { panic!("expected a match") };
+        let Stmt::Assign(assign) = body.remove(0) else { panic!("expected an assignment") };
+        let mut value = assign.value;
+        zero_out(&mut value);
+        assert_eq!(value, Exp::Variant("Shape".to_string(), "Rect".to_string(), vec![intz(1), var("w")], FI::zero()));
+
+        assert_eq!(match_stmt.value, Exp::Var("s".to_string(), FI::new(1, 110)));
+        let arms: Vec<(Option<&str>, Vec<&str>, usize)> = match_stmt
+            .arms
+            .iter()
+            .map(|arm| (arm.variant.as_deref(), arm.bindings.iter().map(|b| b.as_str()).collect(), arm.body.len()))
+            .collect();
+        assert_eq!(arms, vec![(Some("Circle"), vec!["_"], 0), (Some("Rect"), vec!["w", "h"], 1), (None, vec![], 0)]);
+
+        let mut empty = parse_expression(&mut tokenize("Shape::Empty + 1", 0).unwrap().iter().peekable(), Precedence::Lowest).unwrap();
+        zero_out(&mut empty);
+        assert_eq!(empty, add(Exp::Variant("Shape".to_string(), "Empty".to_string(), Vec::new(), FI::zero()), intz(1)));
+    }
+
+    #[test]
+    fn test_parse_array_type() {
+        let input = "[[u64; 3]; 2u64]";
+        let tokens = tokenize(input, 0).unwrap();
+        let mut ti = tokens.iter().peekable();
+        let ttype = parse_type(&mut ti).unwrap();
+        assert_eq!(ttype.fi(), FI::new(input.len(), 0));
+        let u64_3 = Type_::Array(Box::new(Type_::U64(FI::zero())), 3, FI::zero());
+        assert_eq!(ttype.zero(), Type_::Array(Box::new(u64_3), 2, FI::zero()));
+
+        let tokens = tokenize("*[u8; 4]", 0).unwrap();
+        let mut ti = tokens.iter().peekable();
+        let u8_4 = Type_::Array(Box::new(Type_::U8(FI::zero())), 4, FI::zero());
+        assert_eq!(parse_type(&mut ti).unwrap().zero(), Type_::Ptr(Box::new(u8_4), FI::zero()));
+
+        let tokens = tokenize("[u64; 0]", 0).unwrap();
+        let mut ti = tokens.iter().peekable();
+        assert!(parse_type(&mut ti).is_err());
+    }
+
+    #[test]
+    fn test_parse_else_if() {
+        let input = r#"if (a) { x = 1; } else if (b) { x = 2; } else if (c) { x = 3; } else { x = 4; }"#;
+        let tokens = tokenize(input, 0).unwrap();
+        let mut ti = tokens.iter().peekable();
+
+        let if_stmt = parse_if(&mut ti, &mut Vec::new());
+
+        if let Err(e) = if_stmt {
+            e.diagnostic().pretty_print(&sources(input));
+            panic!("parse error");
+        }
+        let if_stmt = if_stmt.unwrap();
+        assert_eq!(if_stmt.fi, FI::new(input.len(), 0));
+
+        let mut branch = &if_stmt;
+        for name in ["b", "c"] {
+            branch = match branch.else_body.as_slice() {
+                [Stmt::If(else_if)] => else_if,
+                other => panic!("expected else if, got {:?}", other),
+            };
+            assert!(matches!(&branch.condition, Exp::Var(n, _) if n == name));
+        }
+        assert!(matches!(branch.else_body.as_slice(), [Stmt::Assign(_)]));
+    }
+
+    #[test]
+    fn test_parse_for() {
+        let input = "for (i: u64 = 0; i < n; i = i + 1) { f(i); }";
+        let tokens = tokenize(input, 0).unwrap();
+        let mut ti = tokens.iter().peekable();
+
+        let for_stmt = parse_for(&mut ti, &mut Vec::new());
+
+        if let Err(e) = for_stmt {
+            e.diagnostic().pretty_print(&sources(input));
+            panic!("parse error");
+        }
+        let mut for_stmt = for_stmt.unwrap();
+        assert_eq!(for_stmt.fi, FI::new(input.len(), 0));
+        assert_eq!(for_stmt.init.fi, FI::new(11, 5));
+        assert_eq!(for_stmt.step.fi, FI::new(9, 24));
+        assert_eq!(for_stmt.init.name, "i");
+        assert_eq!(for_stmt.init.ttype, Type_::U64(FI::new(3, 8)));
+        assert_eq!(for_stmt.body.len(), 1);
+
+        zero_out(&mut for_stmt.init.value);
+        zero_out(&mut for_stmt.condition);
+        zero_out(&mut for_stmt.step.target);
+        zero_out(&mut for_stmt.step.value);
+        assert_eq!(for_stmt.init.value, intz(0));
+        assert_eq!(for_stmt.condition, lt(var("i"), var("n")));
+        assert_eq!(for_stmt.step.target, var("i"));
+        assert_eq!(for_stmt.step.value, add(var("i"), intz(1)));
+    }
+
     #[test]
     fn test_parse_parameters() {
         let input = "(x: u64, y: u64, z: u64)";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, 0).unwrap();
         let expected = vec![
             Parameter {
                 name: "x".to_string(),
@@ -645,7 +1506,7 @@ mod test {
         let p = parse_params(&mut ti);
 
         if let Err(e) = p {
-            e.pretty_print(input);
+            e.diagnostic().pretty_print(&sources(input));
             panic!("parse error");
         }
         let mut p = p.unwrap();
@@ -679,15 +1540,30 @@ mod test {
             fi: FI::new(100, 0),
         };
 
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, 0).unwrap();
         let mut ti = tokens.iter().peekable();
         let a = parse_asm(&mut ti);
 
         if let Err(e) = a {
-            e.pretty_print(input);
+            e.diagnostic().pretty_print(&sources(input));
             panic!("parse error");
         }
 
         assert_eq!(a, Ok(expected));
     }
+
+    #[test]
+    fn test_parse_error_recovery() {
+        let input = "fn main() -> u64 {\n  let a: u64 = 1 +;\n  if (a == ) { b = 3; }\n  while (x) { y = ; z = 1; }\n  b = 2;\n}\nglobal g: i64 = ;\nfn f() -> i64 {\n  return 1\n}\nfn h() -> i64 { let x: i64 = 1; }";
+        let errors = parse_program(tokenize(input, 0).unwrap()).unwrap_err();
+        let got: Vec<(&str, usize)> = errors.iter().map(|e| (e.expected.as_str(), e.fi().offset)).collect();
+        let expected = vec![
+            ("expression", input.find(";\n  if").unwrap()),
+            ("expression", input.find(") {").unwrap()),
+            ("expression", input.find("; z").unwrap()),
+            ("expression", input.find(";\nfn f").unwrap()),
+            (";", input.find("}\nfn h").unwrap()),
+        ];
+        assert_eq!(got, expected);
+    }
 }
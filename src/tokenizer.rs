@@ -1,6 +1,7 @@
 use std::iter::Peekable;
 use std::str::Chars;
-use crate::file_info::{FileInfo, FI, underline_error};
+use crate::diagnostic::Diagnostic;
+use crate::file_info::{FileId, FileInfo, FI};
 
 // aliases to make code more consise
 pub type TT = TokenType;
@@ -18,6 +19,22 @@ impl Token {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct LexError {
+    pub msg: String,
+    pub fi: FI,
+}
+
+impl LexError {
+    pub fn diagnostic(&self) -> Box<Diagnostic> {
+        Diagnostic::new(&self.msg, self.fi)
+    }
+}
+
+fn lex_error<T>(msg: String, fi: FI) -> Result<T, LexError> {
+    Err(LexError { msg, fi })
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     EOF,
@@ -26,6 +43,15 @@ pub enum TokenType {
     Keyword(Keyword),
     U64(u64),
     I64(i64),
+    U32(u32),
+    I32(i32),
+    U16(u16),
+    I16(i16),
+    U8(u8),
+    I8(i8),
+    F64(f64),
+    Str(Vec<u8>),
+    Char(u8),
     LParen,
     RParen,
     LBrace,
@@ -33,14 +59,28 @@ pub enum TokenType {
     LBracket,
     RBracket,
     ReturnArrow,
+    FatArrow,
     Comma,
     Semicolon,
     Colon,
+    DoubleColon,
+    Dot,
     Assign,
     Eq,
     NotEq,
     Lt,
     Gt,
+    LtEq,
+    GtEq,
+    And,
+    Or,
+    Bang,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
     Plus,
     Minus,
     Asterisk,
@@ -59,13 +99,31 @@ pub enum Keyword {
     Else,
     While,
     Do,
+    For,
+    Break,
+    Continue,
     Return,
     Let,
     ASM,
+    True,
+    False,
+    As,
+    Struct,
+    Enum,
+    Match,
 
     // primitive types
     U64,
     I64,
+    U32,
+    I32,
+    U16,
+    I16,
+    U8,
+    I8,
+    F64,
+    Bool,
+    Str,
 }
 
 fn keyword_or_ident(ident: &str) -> TokenType {
@@ -76,52 +134,76 @@ fn keyword_or_ident(ident: &str) -> TokenType {
         "else" => TT::Keyword(KW::Else),
         "while" => TT::Keyword(KW::While),
         "do" => TT::Keyword(KW::Do),
+        "for" => TT::Keyword(KW::For),
+        "break" => TT::Keyword(KW::Break),
+        "continue" => TT::Keyword(KW::Continue),
         "return" => TT::Keyword(KW::Return),
         "let" => TT::Keyword(KW::Let),
         "asm" => TT::Keyword(KW::ASM),
         "global" => TT::Keyword(KW::Global),
+        "true" => TT::Keyword(KW::True),
+        "false" => TT::Keyword(KW::False),
+        "as" => TT::Keyword(KW::As),
+        "struct" => TT::Keyword(KW::Struct),
+        "enum" => TT::Keyword(KW::Enum),
+        "match" => TT::Keyword(KW::Match),
         // primitive types
         "u64" => TT::Keyword(KW::U64),
         "i64" => TT::Keyword(KW::I64),
+        "u32" => TT::Keyword(KW::U32),
+        "i32" => TT::Keyword(KW::I32),
+        "u16" => TT::Keyword(KW::U16),
+        "i16" => TT::Keyword(KW::I16),
+        "u8" => TT::Keyword(KW::U8),
+        "i8" => TT::Keyword(KW::I8),
+        "f64" => TT::Keyword(KW::F64),
+        "bool" => TT::Keyword(KW::Bool),
+        "str" => TT::Keyword(KW::Str),
         _ => TT::Ident(ident.to_string()),
     }
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
+pub fn tokenize(input: &str, file: FileId) -> Result<Vec<Token>, LexError> {
     let mut tokens: Vec<Token> = Vec::new();
-    let mut fi = FI::zero();
+    let mut fi = FI::start_of(file);
     let mut chars = input.chars().peekable();
 
     while let Some(c) = chars.peek() {
         match c {
             ' ' | '\t' | '\n' => tokenize_whitespace(&mut chars, &mut fi, &mut tokens),
             '0'..='9' => {
-                let start_fi = fi.clone();
-                let int = tokenize_num(&mut chars, &mut fi);
+                let start_fi = fi;
+                let int = tokenize_num(&mut chars, &mut fi)?;
                 tokens.push(Token::new(int, fi.len_diff(&start_fi)));
             }
             'a'..='z' | 'A'..='Z' | '_' => {
-                let start = fi.clone();
+                let start = fi;
                 let ident = tokenize_ident(&mut chars, &mut fi);
                 tokens.push(Token::new(ident, fi.len_diff(&start)));
             }
+            '"' => {
+                let start = fi;
+                let string = tokenize_string(&mut chars, &mut fi)?;
+                tokens.push(Token::new(string, fi.len_diff(&start)));
+            }
+            '\'' => {
+                let start = fi;
+                let char = tokenize_char(&mut chars, &mut fi)?;
+                tokens.push(Token::new(char, fi.len_diff(&start)));
+            }
             '(' | ')' | '{' | '}' | '[' | ']' | ',' | ';' | '=' | '+' | '-' | '*' | '/' | '%'
-            | '<' | '>' | '!' | ':' => {
-                let start = fi.clone();
+            | '<' | '>' | '!' | ':' | '&' | '|' | '^' | '~' | '.' => {
+                let start = fi;
                 let simbol = tokenize_simbol(&mut chars, &mut fi);
                 tokens.push(Token::new(simbol, fi.len_diff(&start)));
             }
-            _ => {
-                println!("{}", underline_error(input, &fi));
-                println!("fi: {:?}", fi);
-                panic!("Unknown character: {}", c);
-            }
+            _ => return lex_error(format!("Unknown character '{}'", c), FI { length: c.len_utf8(), ..fi }),
         }
     }
 
-    tokens.push(Token::new(TT::EOF, FI::new(0, input.len())));
+    tokens.push(Token::new(TT::EOF, FI { length: 0, ..fi }));
 
-    tokens
+    Ok(tokens)
 }
 
 fn tokenize_simbol(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> TokenType {
@@ -135,13 +217,26 @@ fn tokenize_simbol(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> TokenType
         '[' => TT::LBracket,
         ']' => TT::RBracket,
         ';' => TT::Semicolon,
-        ':' => TT::Colon,
+        ':' => {
+            if let Some(':') = chars.peek() {
+                chars.next();
+                fi.inc();
+                TT::DoubleColon
+            } else {
+                TT::Colon
+            }
+        }
         ',' => TT::Comma,
+        '.' => TT::Dot,
         '=' => {
             if let Some('=') = chars.peek() {
                 chars.next();
                 fi.inc();
                 TT::Eq
+            } else if let Some('>') = chars.peek() {
+                chars.next();
+                fi.inc();
+                TT::FatArrow
             } else {
                 TT::Assign
             }
@@ -152,7 +247,25 @@ fn tokenize_simbol(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> TokenType
                 fi.inc();
                 TT::NotEq
             } else {
-                TT::Illegal
+                TT::Bang
+            }
+        }
+        '&' => {
+            if let Some('&') = chars.peek() {
+                chars.next();
+                fi.inc();
+                TT::And
+            } else {
+                TT::Ampersand
+            }
+        }
+        '|' => {
+            if let Some('|') = chars.peek() {
+                chars.next();
+                fi.inc();
+                TT::Or
+            } else {
+                TT::Pipe
             }
         }
         '+' => TT::Plus,
@@ -176,8 +289,8 @@ fn tokenize_simbol(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> TokenType
                         '\n' => break,
                         _ => {
                             comment.push(*c);
+                            fi.advance(*c);
                             chars.next();
-                            fi.inc();
                         }
                     }
                 }
@@ -187,13 +300,40 @@ fn tokenize_simbol(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> TokenType
             }
         }
         '%' => TT::Percent,
-        '<' => TT::Lt,
-        '>' => TT::Gt,
+        '<' => {
+            if let Some('=') = chars.peek() {
+                chars.next();
+                fi.inc();
+                TT::LtEq
+            } else if let Some('<') = chars.peek() {
+                chars.next();
+                fi.inc();
+                TT::ShiftLeft
+            } else {
+                TT::Lt
+            }
+        }
+        '>' => {
+            if let Some('=') = chars.peek() {
+                chars.next();
+                fi.inc();
+                TT::GtEq
+            } else if let Some('>') = chars.peek() {
+                chars.next();
+                fi.inc();
+                TT::ShiftRight
+            } else {
+                TT::Gt
+            }
+        }
+        '^' => TT::Caret,
+        '~' => TT::Tilde,
         _ => TT::Illegal,
     }
 }
 
-fn tokenize_num(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> TokenType {
+fn tokenize_num(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> Result<TokenType, LexError> {
+    let start = *fi;
     let mut num = String::new();
     while let Some(c) = chars.peek() {
         match c {
@@ -205,26 +345,156 @@ fn tokenize_num(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> TokenType {
             _ => break,
         }
     }
-    if let Some(c) = chars.peek() {
-        match c {
-            'i' => {
-                expect(chars, "i64", fi);
-                return TT::I64(num.parse::<i64>().unwrap());
+    // a fraction makes it an f64, the dot must be followed by a digit so `a[1].len` still works
+    let mut lookahead = chars.clone();
+    let is_decimal = lookahead.next() == Some('.') && lookahead.peek().is_some_and(|c| c.is_ascii_digit());
+    if is_decimal {
+        num.push('.');
+        chars.next();
+        fi.inc();
+        while let Some(c) = chars.peek() {
+            if !c.is_ascii_digit() {
+                break;
             }
-            'u' => {
-                expect(chars, "u64", fi);
-                return TT::U64(num.parse::<u64>().unwrap());
+            num.push(*c);
+            chars.next();
+            fi.inc();
+        }
+    }
+    // the type suffix, `12u8`, without one the literal is an i64 or an f64 if it has a fraction
+    let mut suffix = String::new();
+    if let Some('i' | 'u' | 'f') = chars.peek() {
+        while let Some(c) = chars.peek() {
+            if !c.is_ascii_alphanumeric() {
+                break;
             }
-            'f' => {
-                expect(chars, "f64", fi);
+            suffix.push(*c);
+            chars.next();
+            fi.inc();
+        }
+    }
+    let fi = fi.len_diff(&start);
+    let tt = match suffix.as_str() {
+        "" | "f64" if is_decimal => TT::F64(parse_num(&num, "f64", fi)?),
+        _ if is_decimal => return lex_error(format!("Number {} with a fraction can not be {}", num, suffix), fi),
+        "f64" => TT::F64(parse_num(&num, &suffix, fi)?),
+        "" | "i64" => TT::I64(parse_signed(&num, "i64", fi)?),
+        "u64" => TT::U64(parse_num(&num, &suffix, fi)?),
+        "u32" => TT::U32(parse_num(&num, &suffix, fi)?),
+        "i32" => TT::I32(parse_signed(&num, &suffix, fi)?),
+        "u16" => TT::U16(parse_num(&num, &suffix, fi)?),
+        "i16" => TT::I16(parse_signed(&num, &suffix, fi)?),
+        "u8" => TT::U8(parse_num(&num, &suffix, fi)?),
+        "i8" => TT::I8(parse_signed(&num, &suffix, fi)?),
+        _ => return lex_error(format!("Unknown number suffix '{}'", suffix), fi),
+    };
+    Ok(tt)
+}
+
+fn parse_num<T: std::str::FromStr>(num: &str, type_name: &str, fi: FI) -> Result<T, LexError> {
+    match num.parse::<T>() {
+        Ok(n) => Ok(n),
+        Err(_) => lex_error(format!("Number {} does not fit in {}", num, type_name), fi),
+    }
+}
+
+/// The magnitude of the minimum, `128i8`, is let through wrapped to the minimum, the parser
+/// only accepts it with a `-` right in front.
+fn parse_signed<T: TryFrom<i128>>(num: &str, type_name: &str, fi: FI) -> Result<T, LexError> {
+    let n: i128 = parse_num(num, type_name, fi)?;
+    match T::try_from(n).or_else(|_| T::try_from(-n)) {
+        Ok(n) => Ok(n),
+        Err(_) => lex_error(format!("Number {} does not fit in {}", num, type_name), fi),
+    }
+}
+
+/// A string literal with its escapes resolved, the bytes are what ends up in the binary.
+fn tokenize_string(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> Result<TokenType, LexError> {
+    let start = *fi;
+    chars.next(); // opening quote
+    fi.inc();
+    let mut bytes = Vec::new();
+    loop {
+        let Some(c) = chars.next() else {
+            return lex_error("Unterminated string literal".to_string(), fi.len_diff(&start));
+        };
+        fi.advance(c);
+        match c {
+            '"' => break,
+            '\\' => bytes.push(tokenize_escape(chars, fi)?),
+            _ => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
             }
-            _ => {}
         }
     }
-    
+    Ok(TT::Str(bytes))
+}
 
+/// A character literal is a single byte, `'a'` or an escape like `'\n'`.
+fn tokenize_char(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> Result<TokenType, LexError> {
+    let start = *fi;
+    chars.next(); // opening quote
+    fi.inc();
+    let Some(c) = chars.next() else {
+        return lex_error("Unterminated character literal".to_string(), fi.len_diff(&start));
+    };
+    fi.advance(c);
+    let byte = match c {
+        '\\' => tokenize_escape(chars, fi)?,
+        '\'' => return lex_error("Empty character literal".to_string(), fi.len_diff(&start)),
+        _ if c.is_ascii() => c as u8,
+        _ => return lex_error(format!("Character literal '{}' does not fit in a byte", c), fi.len_diff(&start)),
+    };
+    if chars.next() != Some('\'') {
+        return lex_error("Unterminated character literal".to_string(), fi.len_diff(&start));
+    }
+    fi.inc();
+    Ok(TT::Char(byte))
+}
 
-    TT::I64(num.parse::<i64>().unwrap())
+/// The byte for an escape sequence in a string or character literal, the `\\` is already consumed.
+fn tokenize_escape(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> Result<u8, LexError> {
+    // the span starts at the backslash
+    let start = FI { length: fi.length - 1, offset: fi.offset - 1, ..*fi };
+    let Some(escape) = chars.next() else {
+        return lex_error("Unterminated escape sequence".to_string(), fi.len_diff(&start));
+    };
+    fi.advance(escape);
+    let byte = match escape {
+        'n' => b'\n',
+        't' => b'\t',
+        '"' => b'"',
+        '\'' => b'\'',
+        '\\' => b'\\',
+        'x' => {
+            let hex: String = chars.by_ref().take(2).collect();
+            hex.chars().for_each(|c| fi.advance(c));
+            if hex.len() != 2 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return lex_error(format!("Invalid escape sequence \\x{}", hex), fi.len_diff(&start));
+            }
+            u8::from_str_radix(&hex, 16).unwrap()
+        }
+        _ => return lex_error(format!("Unknown escape sequence \\{}", escape), fi.len_diff(&start)),
+    };
+    Ok(byte)
+}
+
+/// Inverse of `tokenize_string`, used to print string tokens back as source.
+fn escape_string(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for b in bytes {
+        match b {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'"' => out.push_str("\\\""),
+            b'\'' => out.push_str("\\'"),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(*b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out
 }
 
 fn tokenize_ident(chars: &mut Peekable<Chars>, fi: &mut FileInfo) -> TokenType {
@@ -249,29 +519,12 @@ fn tokenize_whitespace(chars: &mut Peekable<Chars>, fi: &mut FileInfo, tokens: &
             '\n' => TT::Newline,
             _ => break,
         };
-        tokens.push(Token::new(tt, FI::new(1, fi.offset)));
+        tokens.push(Token::new(tt, FI { length: 1, ..*fi }));
         chars.next();
         fi.inc();
     }
 }
 
-fn expect(chars: &mut Peekable<Chars>, expected: &str, fi: &mut FileInfo) {
-    let mut got = String::new();
-    for c in expected.chars() {
-        match chars.next() {
-            Some(x) => {
-                got.push(x);
-                fi.inc();
-                if x != c {
-                    println!("{}", underline_error(&format!("Expected: '{}', got: '{}'", expected, got), &fi));
-                    panic!("Expected: '{}', got: '{}'", expected, got);
-                }
-            }
-            None => panic!("Expected: '{}', got '{}' + EOF", expected, got),
-        }
-    }
-}
-
 impl TokenType {
     pub fn string(&self) -> String {
         let tmp: String;
@@ -286,18 +539,72 @@ impl TokenType {
                 KW::Else => "else",
                 KW::While => "while",
                 KW::Do => "do",
+                KW::For => "for",
+                KW::Break => "break",
+                KW::Continue => "continue",
                 KW::Return => "return",
                 KW::Let => "let",
                 KW::ASM => "asm",
+                KW::True => "true",
+                KW::False => "false",
+                KW::As => "as",
+                KW::Struct => "struct",
+                KW::Enum => "enum",
+                KW::Match => "match",
                 KW::U64 => "u64",
                 KW::I64 => "i64",
+                KW::U32 => "u32",
+                KW::I32 => "i32",
+                KW::U16 => "u16",
+                KW::I16 => "i16",
+                KW::U8 => "u8",
+                KW::I8 => "i8",
+                KW::F64 => "f64",
+                KW::Bool => "bool",
+                KW::Str => "str",
             },
             TT::U64(i) => {
                 tmp = i.to_string();
                 &tmp
             }
             TT::I64(i) => {
-                tmp = i.to_string();
+                tmp = i.unsigned_abs().to_string();
+                &tmp
+            }
+            TT::U32(i) => {
+                tmp = format!("{}u32", i);
+                &tmp
+            }
+            TT::I32(i) => {
+                tmp = format!("{}i32", i.unsigned_abs());
+                &tmp
+            }
+            TT::U16(i) => {
+                tmp = format!("{}u16", i);
+                &tmp
+            }
+            TT::I16(i) => {
+                tmp = format!("{}i16", i.unsigned_abs());
+                &tmp
+            }
+            TT::U8(i) => {
+                tmp = format!("{}u8", i);
+                &tmp
+            }
+            TT::I8(i) => {
+                tmp = format!("{}i8", i.unsigned_abs());
+                &tmp
+            }
+            TT::F64(f) => {
+                tmp = format!("{:?}", f);
+                &tmp
+            }
+            TT::Str(bytes) => {
+                tmp = format!("\"{}\"", escape_string(bytes));
+                &tmp
+            }
+            TT::Char(byte) => {
+                tmp = format!("'{}'", escape_string(&[*byte]));
                 &tmp
             }
             TT::LParen => "(",
@@ -307,14 +614,28 @@ impl TokenType {
             TT::LBracket => "[",
             TT::RBracket => "]",
             TT::ReturnArrow => "->",
+            TT::FatArrow => "=>",
             TT::Comma => ",",
             TT::Semicolon => ";",
             TT::Colon => ":",
+            TT::DoubleColon => "::",
+            TT::Dot => ".",
             TT::Assign => "=",
             TT::Eq => "==",
             TT::NotEq => "!=",
             TT::Lt => "<",
             TT::Gt => ">",
+            TT::LtEq => "<=",
+            TT::GtEq => ">=",
+            TT::And => "&&",
+            TT::Or => "||",
+            TT::Bang => "!",
+            TT::Ampersand => "&",
+            TT::Pipe => "|",
+            TT::Caret => "^",
+            TT::Tilde => "~",
+            TT::ShiftLeft => "<<",
+            TT::ShiftRight => ">>",
             TT::Plus => "+",
             TT::Minus => "-",
             TT::Asterisk => "*",
@@ -340,6 +661,11 @@ mod test {
         Token::new(tt, FI::new(length, offset))
     }
 
+    fn lex_error(input: &str) -> (String, FI) {
+        let e = tokenize(input, 0).unwrap_err();
+        (e.msg, e.fi)
+    }
+
     #[test]
     fn test_tokenize() {
         let input = "fn add(x, y) { x + y }";
@@ -366,7 +692,7 @@ mod test {
             token(TT::EOF, 0, 22),
         ];
 
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, 0).unwrap();
 
         assert_eq!(tokens, expected);
     }
@@ -383,9 +709,225 @@ mod test {
             token(TT::EOF, 0, 6),
         ];
 
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, 0).unwrap();
+
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_tokenize_comparisons() {
+        let input = "a<=b>=c<d>e";
+        let expected = vec![
+            token(TT::Ident("a".to_string()), 1, 0),
+            token(TT::LtEq, 2, 1),
+            token(TT::Ident("b".to_string()), 1, 3),
+            token(TT::GtEq, 2, 4),
+            token(TT::Ident("c".to_string()), 1, 6),
+            token(TT::Lt, 1, 7),
+            token(TT::Ident("d".to_string()), 1, 8),
+            token(TT::Gt, 1, 9),
+            token(TT::Ident("e".to_string()), 1, 10),
+            token(TT::EOF, 0, 11),
+        ];
+
+        let tokens = tokenize(input, 0).unwrap();
+
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_tokenize_logical_operators() {
+        let input = "!a&&b||c";
+        let expected = vec![
+            token(TT::Bang, 1, 0),
+            token(TT::Ident("a".to_string()), 1, 1),
+            token(TT::And, 2, 2),
+            token(TT::Ident("b".to_string()), 1, 4),
+            token(TT::Or, 2, 5),
+            token(TT::Ident("c".to_string()), 1, 7),
+            token(TT::EOF, 0, 8),
+        ];
+
+        let tokens = tokenize(input, 0).unwrap();
+
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_tokenize_bitwise_operators() {
+        let input = "a<<b>>c&d|e^~f";
+        let expected = vec![
+            token(TT::Ident("a".to_string()), 1, 0),
+            token(TT::ShiftLeft, 2, 1),
+            token(TT::Ident("b".to_string()), 1, 3),
+            token(TT::ShiftRight, 2, 4),
+            token(TT::Ident("c".to_string()), 1, 6),
+            token(TT::Ampersand, 1, 7),
+            token(TT::Ident("d".to_string()), 1, 8),
+            token(TT::Pipe, 1, 9),
+            token(TT::Ident("e".to_string()), 1, 10),
+            token(TT::Caret, 1, 11),
+            token(TT::Tilde, 1, 12),
+            token(TT::Ident("f".to_string()), 1, 13),
+            token(TT::EOF, 0, 14),
+        ];
+
+        let tokens = tokenize(input, 0).unwrap();
+
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_tokenize_match_arms() {
+        let input = "S::A(x)=>y:z=x";
+        let expected = vec![
+            token(TT::Ident("S".to_string()), 1, 0),
+            token(TT::DoubleColon, 2, 1),
+            token(TT::Ident("A".to_string()), 1, 3),
+            token(TT::LParen, 1, 4),
+            token(TT::Ident("x".to_string()), 1, 5),
+            token(TT::RParen, 1, 6),
+            token(TT::FatArrow, 2, 7),
+            token(TT::Ident("y".to_string()), 1, 9),
+            token(TT::Colon, 1, 10),
+            token(TT::Ident("z".to_string()), 1, 11),
+            token(TT::Assign, 1, 12),
+            token(TT::Ident("x".to_string()), 1, 13),
+            token(TT::EOF, 0, 14),
+        ];
+
+        let tokens = tokenize(input, 0).unwrap();
+
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_tokenize_strings() {
+        let input = r#""hi\n\t\"\\\x41\x00".len"#;
+        let expected = vec![
+            token(TT::Str(b"hi\n\t\"\\A\x00".to_vec()), 20, 0),
+            token(TT::Dot, 1, 20),
+            token(TT::Ident("len".to_string()), 3, 21),
+            token(TT::EOF, 0, 24),
+        ];
+
+        let tokens = tokenize(input, 0).unwrap();
 
         assert_eq!(tokens, expected);
+        assert_eq!(tokens[0].token_type.string(), r#""hi\n\t\"\\A\x00""#);
+        assert_eq!(tokenize(r#""""#, 0).unwrap()[0].token_type, TT::Str(Vec::new()));
+    }
+
+    #[test]
+    fn test_tokenize_unknown_escape() {
+        assert_eq!(lex_error(r#""\q""#), ("Unknown escape sequence \\q".to_string(), FI::new(2, 1)));
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_string() {
+        assert_eq!(lex_error(r#""abc"#), ("Unterminated string literal".to_string(), FI::new(4, 0)));
+    }
+
+    #[test]
+    fn test_tokenize_number_suffixes() {
+        let types: Vec<TT> = tokenize("1 2u64 3i64 4u32 5i32 6u16 7i16 255u8 127i8", 0).unwrap()
+            .into_iter()
+            .map(|t| t.token_type)
+            .filter(|t| *t != TT::Whitespace)
+            .collect();
+        let expected = vec![
+            TT::I64(1),
+            TT::U64(2),
+            TT::I64(3),
+            TT::U32(4),
+            TT::I32(5),
+            TT::U16(6),
+            TT::I16(7),
+            TT::U8(255),
+            TT::I8(127),
+            TT::EOF,
+        ];
+        assert_eq!(types, expected);
+        assert_eq!(tokenize("x as u16", 0).unwrap()[2].token_type, TT::Keyword(KW::As));
+    }
+
+    #[test]
+    fn test_tokenize_floats() {
+        let types: Vec<TT> = tokenize("2.75 1f64 2.5f64 a[1].len", 0).unwrap()
+            .into_iter()
+            .map(|t| t.token_type)
+            .filter(|t| *t != TT::Whitespace)
+            .collect();
+        let expected = vec![
+            TT::F64(2.75),
+            TT::F64(1.0),
+            TT::F64(2.5),
+            TT::Ident("a".to_string()),
+            TT::LBracket,
+            TT::I64(1),
+            TT::RBracket,
+            TT::Dot,
+            TT::Ident("len".to_string()),
+            TT::EOF,
+        ];
+        assert_eq!(types, expected);
+        assert_eq!(tokenize("1.5", 0).unwrap()[0].fi, FI::new(3, 0));
+        assert_eq!(TT::F64(1.0).string(), "1.0");
+    }
+
+    #[test]
+    fn test_tokenize_fraction_with_integer_suffix() {
+        assert_eq!(lex_error("1.5u8"), ("Number 1.5 with a fraction can not be u8".to_string(), FI::new(5, 0)));
+    }
+
+    #[test]
+    fn test_tokenize_number_out_of_range() {
+        assert_eq!(lex_error("256u8"), ("Number 256 does not fit in u8".to_string(), FI::new(5, 0)));
+    }
+
+    #[test]
+    fn test_tokenize_unknown_suffix() {
+        assert_eq!(lex_error("1u7"), ("Unknown number suffix 'u7'".to_string(), FI::new(3, 0)));
+    }
+
+    #[test]
+    fn test_tokenize_chars() {
+        let input = r"'a'+'\n' '\'' '\x7f'";
+        let expected = vec![
+            token(TT::Char(b'a'), 3, 0),
+            token(TT::Plus, 1, 3),
+            token(TT::Char(b'\n'), 4, 4),
+            token(TT::Whitespace, 1, 8),
+            token(TT::Char(b'\''), 4, 9),
+            token(TT::Whitespace, 1, 13),
+            token(TT::Char(0x7f), 6, 14),
+            token(TT::EOF, 0, 20),
+        ];
+
+        let tokens = tokenize(input, 0).unwrap();
+
+        assert_eq!(tokens, expected);
+        assert_eq!(tokens[4].token_type.string(), r"'\''");
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_char() {
+        assert_eq!(lex_error("'ab'"), ("Unterminated character literal".to_string(), FI::new(2, 0)));
+    }
+
+    #[test]
+    fn test_tokenize_wide_char() {
+        // 'é' takes two bytes, spans are in bytes
+        assert_eq!(lex_error("'é'"), ("Character literal 'é' does not fit in a byte".to_string(), FI::new(3, 0)));
+    }
+
+    #[test]
+    fn test_tokenize_unknown_character() {
+        assert_eq!(lex_error("a = 1;\nb = $;"), ("Unknown character '$'".to_string(), FI::new(1, 11)));
+        assert_eq!(lex_error("129i8"), ("Number 129 does not fit in i8".to_string(), FI::new(5, 0)));
+        assert_eq!(lex_error("99999999999999999999"), ("Number 99999999999999999999 does not fit in i64".to_string(), FI::new(20, 0)));
+        assert_eq!(lex_error("x = '';"), ("Empty character literal".to_string(), FI::new(2, 4)));
+        assert_eq!(lex_error(r#""ab\x4""#), ("Invalid escape sequence \\x4\"".to_string(), FI::new(4, 3)));
     }
 
     #[test]
@@ -401,8 +943,20 @@ mod test {
             token(TT::EOF, 0, 15),
         ];
 
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, 0).unwrap();
 
         assert_eq!(tokens, expected);
     }
+
+    #[test]
+    fn test_tokenize_byte_offsets() {
+        // offsets are in bytes, so they can slice the input after non-ASCII text
+        let input = "// héllo ✓\nx = \"ü\";";
+        let tokens = tokenize(input, 3).unwrap();
+        let x = tokens.iter().find(|t| t.token_type == TT::Ident("x".to_string())).unwrap();
+        assert_eq!(x.fi, FI { length: 1, offset: input.find('x').unwrap(), file: 3 });
+        let string = tokens.iter().find(|t| matches!(t.token_type, TT::Str(_))).unwrap();
+        assert_eq!(&input[string.fi.offset..string.fi.offset + string.fi.length], "\"ü\"");
+        assert_eq!(tokens.last().unwrap().fi, FI { length: 0, offset: input.len(), file: 3 });
+    }
 }